@@ -0,0 +1,339 @@
+use crate::{ConfigArgs, ConfigCommand, ConflictPolicy};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+/// The name of the project configuration, looked up in the current directory and its parents
+pub const PROJECT_FILE: &str = ".powerfile.toml";
+
+/// Where a setting got its value from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    /// The name of the environment variable
+    Environment(&'static str),
+    Flag,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Environment(name) => write!(f, "${}", name),
+            Source::Flag => write!(f, "command line"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Setting<T> {
+    fn new(value: T) -> Self {
+        Setting {
+            value,
+            source: Source::Default,
+        }
+    }
+
+    fn set(&mut self, value: Option<T>, source: Source) {
+        if let Some(value) = value {
+            *self = Setting { value, source };
+        }
+    }
+
+    /// Overrides the value with a command line flag, if it was given
+    pub fn flag(&mut self, value: Option<T>) {
+        self.set(value, Source::Flag);
+    }
+}
+
+/// Settings shared by the commands, each layer overriding the ones before it: the defaults,
+/// the user configuration, the project configuration, the environment and the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// The most paths `create` makes at once
+    pub limit: Setting<u32>,
+    /// Directory the templates are read from
+    pub templates: Setting<PathBuf>,
+    /// Directory the template index is kept in
+    pub cache: Setting<PathBuf>,
+    /// Bytes reserved for the path of each cached template in the index
+    pub block_size: Setting<usize>,
+    pub on_conflict: Setting<ConflictPolicy>,
+    /// The configuration files that were looked for, and whether they were found
+    pub files: Vec<(PathBuf, bool)>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            limit: Setting::new(100),
+            templates: Setting::new(PathBuf::from("templates")),
+            cache: Setting::new(PathBuf::from("cache")),
+            block_size: Setting::new(128),
+            on_conflict: Setting::new(ConflictPolicy::Skip),
+            files: Vec::new(),
+        }
+    }
+}
+
+// A configuration file, every setting is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct File {
+    limit: Option<u32>,
+    templates: Option<PathBuf>,
+    cache: Option<PathBuf>,
+    block_size: Option<usize>,
+    on_conflict: Option<ConflictPolicy>,
+}
+
+impl Config {
+    /// Reads the user and project configuration and the environment of this process
+    pub fn load() -> Result<Config, String> {
+        let mut files = Vec::new();
+        if let Some(dir) = user_config_dir() {
+            files.push(dir.join("powerfile").join("config.toml"));
+        }
+        let cwd = std::env::current_dir().map_err(|err| err.to_string())?;
+        if let Some(project) = find_project_file(&cwd) {
+            files.push(project);
+        }
+
+        Config::layered(&files, |name| std::env::var(name).ok())
+    }
+
+    /// The defaults overridden by `files` in order, then by the variables `env` returns
+    pub fn layered(
+        files: &[PathBuf],
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Config, String> {
+        let mut config = Config::default();
+
+        for path in files {
+            let text = match fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    config.files.push((path.clone(), false));
+                    continue;
+                }
+                Err(err) => return Err(format!("{}: {}", path.display(), err)),
+            };
+            let file: File =
+                toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+            config.apply(file, path);
+            config.files.push((path.clone(), true));
+        }
+
+        config.apply_env(env)?;
+        Ok(config)
+    }
+
+    // Paths in a file are relative to the directory it is in
+    fn apply(&mut self, file: File, path: &Path) {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let source = || Source::File(path.to_path_buf());
+
+        self.limit.set(file.limit, source());
+        self.templates
+            .set(file.templates.map(|p| dir.join(p)), source());
+        self.cache.set(file.cache.map(|p| dir.join(p)), source());
+        self.block_size.set(file.block_size, source());
+        self.on_conflict.set(file.on_conflict, source());
+    }
+
+    fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        fn parse<T: FromStr>(name: &str, value: Option<String>) -> Result<Option<T>, String>
+        where
+            T::Err: fmt::Display,
+        {
+            value
+                .map(|value| value.parse().map_err(|err| format!("${}: {}", name, err)))
+                .transpose()
+        }
+
+        let var = |name| (env(name), Source::Environment(name));
+
+        let (value, source) = var("POWERFILE_LIMIT");
+        self.limit.set(parse("POWERFILE_LIMIT", value)?, source);
+        let (value, source) = var("POWERFILE_TEMPLATES");
+        self.templates.set(value.map(PathBuf::from), source);
+        let (value, source) = var("POWERFILE_CACHE");
+        self.cache.set(value.map(PathBuf::from), source);
+        let (value, source) = var("POWERFILE_BLOCK_SIZE");
+        self.block_size
+            .set(parse("POWERFILE_BLOCK_SIZE", value)?, source);
+
+        let (value, source) = var("POWERFILE_ON_CONFLICT");
+        let policy = value
+            .map(|value| {
+                ConflictPolicy::from_str(&value, true)
+                    .map_err(|err| format!("$POWERFILE_ON_CONFLICT: {}", err))
+            })
+            .transpose()?;
+        self.on_conflict.set(policy, source);
+
+        Ok(())
+    }
+}
+
+pub struct ConfigHandler {
+    args: ConfigArgs,
+    config: Config,
+}
+
+impl ConfigHandler {
+    pub fn new(args: ConfigArgs, config: Config) -> Self {
+        ConfigHandler { args, config }
+    }
+
+    pub fn handle(&self) -> ExitCode {
+        match self.args.command {
+            ConfigCommand::Show => self.show(),
+        }
+    }
+
+    fn show(&self) -> ExitCode {
+        let config = &self.config;
+        let policy = config.on_conflict.value.to_possible_value();
+
+        let settings = [
+            (
+                "limit",
+                config.limit.value.to_string(),
+                &config.limit.source,
+            ),
+            (
+                "templates",
+                config.templates.value.display().to_string(),
+                &config.templates.source,
+            ),
+            (
+                "cache",
+                config.cache.value.display().to_string(),
+                &config.cache.source,
+            ),
+            (
+                "block-size",
+                config.block_size.value.to_string(),
+                &config.block_size.source,
+            ),
+            (
+                "on-conflict",
+                policy
+                    .map(|policy| policy.get_name().to_string())
+                    .unwrap_or_default(),
+                &config.on_conflict.source,
+            ),
+        ];
+
+        let width = settings
+            .iter()
+            .map(|(_, value, _)| value.len())
+            .max()
+            .unwrap_or(0);
+        for (name, value, source) in &settings {
+            println!(
+                "{:<12} {:<width$}  from {}",
+                name,
+                value,
+                source,
+                width = width
+            );
+        }
+
+        println!();
+        for (path, found) in &config.files {
+            let status = if *found { "read" } else { "not found" };
+            println!("{:<12} {}", status, path.display());
+        }
+
+        ExitCode::SUCCESS
+    }
+}
+
+// `$XDG_CONFIG_HOME`, or `~/.config` when it is not set
+fn user_config_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
+
+// The nearest project configuration in `dir` or one of its parents
+fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("project/sub")).unwrap();
+        fs::write(
+            dir.join("user.toml"),
+            "limit = 500\ncache = \"cache\"\non-conflict = \"rename\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("project").join(PROJECT_FILE),
+            "limit = 20\nblock-size = 256\n",
+        )
+        .unwrap();
+
+        let project = find_project_file(&dir.join("project/sub")).unwrap();
+        let files = [
+            dir.join("user.toml"),
+            project.clone(),
+            dir.join("missing.toml"),
+        ];
+        let config = Config::layered(&files, |name| match name {
+            "POWERFILE_ON_CONFLICT" => Some("Overwrite".to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        assert_eq!(
+            (config.limit.value, &config.limit.source),
+            (20, &Source::File(project.clone()))
+        );
+        assert_eq!(config.cache.value, dir.join("cache"));
+        assert_eq!(config.block_size.value, 256);
+        assert_eq!(config.templates.source, Source::Default);
+        assert_eq!(config.on_conflict.value, ConflictPolicy::Overwrite);
+        assert_eq!(
+            config.on_conflict.source,
+            Source::Environment("POWERFILE_ON_CONFLICT")
+        );
+        assert_eq!(
+            config.files.last(),
+            Some(&(dir.join("missing.toml"), false))
+        );
+
+        let mut limit = config.limit.clone();
+        limit.flag(Some(3));
+        assert_eq!((limit.value, limit.source), (3, Source::Flag));
+
+        fs::write(dir.join("user.toml"), "limits = 1\n").unwrap();
+        assert!(Config::layered(&files, |_| None).is_err());
+        assert!(Config::layered(&[], |_| Some("x".to_string())).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use crate::diagnostics;
+use crate::{ConvertArgs, Syntax};
+use powerfile_core::brace::{self, Conversion};
+use powerfile_core::macros::Definitions;
+use powerfile_core::parser;
+use std::process::ExitCode;
+
+pub struct ConvertHandler {
+    args: ConvertArgs,
+}
+
+impl ConvertHandler {
+    pub fn new(args: ConvertArgs) -> Self {
+        ConvertHandler { args }
+    }
+
+    pub fn handle(&self) -> ExitCode {
+        let input = &self.args.input;
+        let conversion = match self.args.from {
+            Syntax::Bash => brace::from_brace(input),
+            Syntax::Powerfile => {
+                let value = match parser::parse(input)
+                    .and_then(|value| Definitions::new().expand(&value))
+                {
+                    Ok(value) => value,
+                    Err(err) => {
+                        diagnostics::report(input, input, &[err]);
+                        return ExitCode::FAILURE;
+                    }
+                };
+
+                brace::to_brace(&value)
+            }
+        };
+
+        match conversion {
+            Ok(Conversion { output, warnings }) => {
+                for warning in warnings {
+                    eprintln!("warning: {}", warning);
+                }
+                println!("{}", output);
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
@@ -1,44 +1,711 @@
-use powerfile_core::interpreter::{Interpreter, SizeInterpreter, TextInterpreter};
-use powerfile_core::parser;
-use crate::CreateArgs;
+use crate::config::Config;
+use crate::diagnostics;
+use crate::journal::{self, Entry, Journal};
+use crate::plan::{self, Action, Kind, Plan, Step};
+use crate::{ConflictPolicy, CreateArgs};
+use powerfile_core::expansion::Expansion;
+use powerfile_core::include::{IncludeOptions, Sources};
+use powerfile_core::interpreter::{InterpretError, Interpreter, SizeInterpreter};
+use powerfile_core::limits::Limits;
+use powerfile_core::macros::Definitions;
+use powerfile_core::parser::{ParseOptions, Value};
+use powerfile_core::resolver::{FileListResolver, SystemEnvironment};
+use powerfile_core::{parser, pattern_file};
+use powerfile_templating::index::{IndexBuildError, TemplateIndex, TemplateOptions};
+use powerfile_templating::render;
+use powerfile_templating::search::TemplateEngine;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 pub struct CreateHandler {
-    args: CreateArgs
+    args: CreateArgs,
+    config: Config,
 }
 
 impl CreateHandler {
-    pub fn handle(&self) {
-        let pattern = &self.args.pattern;
+    pub fn new(args: CreateArgs, mut config: Config) -> Self {
+        config.limit.flag(args.limit);
+        config.on_conflict.flag(args.on_conflict);
+        config.cache.flag(args.cache.clone());
 
-        match parser::parse(pattern) {
-            Ok(value) => {
+        CreateHandler { args, config }
+    }
+
+    /// Creates every expanded path below `--root`, failing when any of them could not be created.
+    ///
+    /// With `--dry-run` the plan is only printed, failing when it has conflicts. Otherwise
+    /// conflicts left by `--on-conflict` abort before anything is created, and when creating
+    /// any path fails everything created before it is removed again.
+    pub fn handle(&self) -> ExitCode {
+        if let Some(path) = &self.args.file {
+            return self.handle_file(path);
+        }
+
+        let pattern = self.args.pattern.as_deref().unwrap_or_default();
+        let compiled = parser::parse_with(pattern, &self.parse_options(None))
+            .and_then(|value| Definitions::new().expand_with(&value, &self.limits()));
+
+        match compiled {
+            Ok(value) => self.create(&[value]),
+            Err(err) => {
+                diagnostics::report(pattern, pattern, &[err]);
+                ExitCode::FAILURE
+            }
+        }
+    }
+
+    fn handle_file(&self, path: &Path) -> ExitCode {
+        let options = IncludeOptions {
+            search_path: self.args.include_dir.clone(),
+        };
+        let sources = match Sources::load(path, &options) {
+            Ok(sources) => sources,
+            Err(err) => {
+                eprintln!("Failed to read pattern file '{}': {}", path.display(), err);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        match pattern_file::parse_sources(&sources, &self.parse_options(path.parent())) {
+            Ok(file) => {
+                diagnostics::warn_sources(&sources, &file.warnings);
+                let values = file
+                    .entries
+                    .into_iter()
+                    .map(|entry| entry.value)
+                    .collect::<Vec<_>>();
+                self.create(&values)
+            }
+            Err(errors) => {
+                diagnostics::report_sources(&sources, &errors);
+                ExitCode::FAILURE
+            }
+        }
+    }
+
+    fn create(&self, values: &[Value]) -> ExitCode {
+        if self.args.debug {
+            for value in values {
                 println!("{:#?}", value);
+            }
+        }
 
-                let start = std::time::Instant::now();
-                let text = TextInterpreter;
-                let size = SizeInterpreter.interpret(&value);
-                if size > self.args.limit {
-                    println!("Pattern size {:#?} exceeds limit of {:#?} ", size, self.args.limit);
+        let Some(expanded) = self.expand(values) else {
+            return ExitCode::FAILURE;
+        };
+        let templates = match self.templates() {
+            Ok(templates) => templates,
+            Err(err) => {
+                eprintln!("Failed to read the template index, use --no-template to create empty files: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // A dry run never asks, conflicts that would be prompted for are shown as such
+        let policy = match (self.args.dry_run, self.config.on_conflict.value) {
+            (true, ConflictPolicy::Prompt) => ConflictPolicy::Error,
+            (_, policy) => policy,
+        };
+        let plan = Plan::new(&self.args.root, &expanded.paths, policy);
+        let (chosen, notes) = self.choose_templates(&plan, templates.as_ref());
+
+        if self.args.dry_run {
+            return print_plan(&plan, &notes);
+        }
+
+        // Nothing is touched unless every conflict has been resolved
+        if plan.conflicts() > 0 {
+            for step in &plan.steps {
+                if let Action::Conflict(reason) = step.action {
+                    eprintln!("'{}': {}", step.path, reason);
                 }
+            }
+            eprintln!(
+                "Aborting, {} conflict(s) found, nothing was created",
+                plan.conflicts()
+            );
+            return ExitCode::FAILURE;
+        }
+
+        let journal_dir = self.args.root.join(journal::DIRECTORY);
+        let mut journal = match Journal::open(&journal_dir) {
+            Ok(journal) => journal,
+            Err(err) => {
+                eprintln!(
+                    "Failed to read the journal in '{}': {}",
+                    journal_dir.display(),
+                    err
+                );
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let new_journal = !journal_dir.exists();
+        let mut entries = Vec::new();
+        let (mut created, mut overwritten, mut renamed) = (0, 0, 0);
+        for (index, step) in plan.steps.iter().enumerate() {
+            let content = match (chosen[index], &templates) {
+                (Some(id), Some(templates)) => templates.render(id, expanded.variables(step)),
+                _ => Ok(String::new()),
+            };
+            let applied = content.and_then(|content| {
+                apply_recorded(
+                    &journal,
+                    &self.args.root,
+                    step,
+                    content.as_bytes(),
+                    &mut entries,
+                )
+            });
+            if let Err(err) = applied {
+                println!("failed    {}: {}", step.target, err);
+                return self.roll_back(&journal, &entries, new_journal);
+            }
+
+            let note = &notes[index];
+            match step.action {
+                Action::Exists => println!("exists    {}", step.path),
+                Action::Skip(reason) => println!("skipped   {} ({})", step.path, reason),
+                Action::Overwrite => {
+                    println!("overwrote {}{}", step.path, note);
+                    overwritten += 1;
+                }
+                Action::Rename => {
+                    println!("renamed   {} -> {}{}", step.path, step.target, note);
+                    renamed += 1;
+                }
+                Action::Create => {
+                    println!("created   {}{}", step.target, note);
+                    created += 1;
+                }
+                Action::Conflict(_) => unreachable!("conflicts abort before apply"),
+            }
+        }
 
-                for line in text.interpret(&value) {
-                    println!("{}", line)
+        println!(
+            "{} created, {} overwritten, {} renamed, {} skipped, {} already existed",
+            created,
+            overwritten,
+            renamed,
+            plan.skipped(),
+            plan.count(Kind::Directory, Action::Exists) + plan.count(Kind::File, Action::Exists)
+        );
+        self.record(&mut journal, entries);
+        ExitCode::SUCCESS
+    }
+
+    // Reverts what this run did after a step failed, the run is not recorded. The content it
+    // stored is removed again, along with the journal when this run started it.
+    fn roll_back(&self, journal: &Journal, entries: &[Entry], new_journal: bool) -> ExitCode {
+        let mut failures = journal::revert(journal, &self.args.root, entries);
+
+        let removed = match new_journal {
+            true => fs::remove_dir_all(journal.dir()),
+            false => journal.remove_unused(),
+        };
+        match removed {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                failures.push(format!("'{}': {}", journal.dir().display(), err))
+            }
+            _ => {}
+        }
+
+        if failures.is_empty() {
+            eprintln!("Rolled back, nothing was changed");
+            return ExitCode::FAILURE;
+        }
+
+        for failure in &failures {
+            eprintln!("{}", failure);
+        }
+        eprintln!(
+            "Rolled back partially, {} path(s) could not be reverted",
+            failures.len()
+        );
+        ExitCode::FAILURE
+    }
+
+    // Keeps what was done in the journal for `undo`, unless nothing changed
+    fn record(&self, journal: &mut Journal, entries: Vec<Entry>) {
+        if entries
+            .iter()
+            .all(|entry| entry.existed && entry.previous.is_none())
+        {
+            return;
+        }
+
+        let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+        let root = fs::canonicalize(&self.args.root).unwrap_or_else(|_| self.args.root.clone());
+        journal.record(command, root, entries);
+
+        if let Err(err) = journal.save() {
+            eprintln!(
+                "warning: Failed to record the operation, it can not be undone: {}",
+                err
+            );
+        }
+    }
+
+    fn expand(&self, values: &[Value]) -> Option<Expanded> {
+        let limits = self.limits();
+
+        // Every pattern is checked on its own, the limit applies to all of them together
+        let size = values.iter().try_fold(0u32, |total, value| {
+            let size = SizeInterpreter.interpret(value)?;
+            total.checked_add(size).ok_or(InterpretError::TooLarge)
+        });
+        match size {
+            Ok(size) if size > self.config.limit.value => {
+                eprintln!(
+                    "Patterns produce {} paths, more than the limit of {}",
+                    size, self.config.limit.value
+                );
+                return None;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("{}", err);
+                return None;
+            }
+        }
+
+        let mut expanded = Expanded::default();
+        for value in values {
+            let mut expansion = match Expansion::with_limits(value, &limits) {
+                Ok(expansion) => expansion,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return None;
                 }
-                eprintln!("{:?}", start.elapsed());
+            };
+
+            while let Some(path) = expansion.next() {
+                let path = path.to_owned();
+                let captures = expansion
+                    .captures()
+                    .into_iter()
+                    .map(|capture| capture.map(str::to_owned));
+                expanded.captures.push(captures.collect());
+                expanded.paths.push(path);
             }
-            Err((msg, span)) => {
-                use ariadne::{ColorGenerator, Label, Report, ReportKind, Source};
+        }
+
+        Some(expanded)
+    }
 
-                let mut colors = ColorGenerator::new();
-                let a = colors.next();
+    // The template of every step, with the note printed after it
+    fn choose_templates(
+        &self,
+        plan: &Plan,
+        templates: Option<&Templates>,
+    ) -> (Vec<Option<usize>>, Vec<String>) {
+        let chosen = plan
+            .steps
+            .iter()
+            .map(|step| templates.and_then(|templates| templates.choose(step, &self.args.tags)))
+            .collect::<Vec<_>>();
+        let notes = chosen
+            .iter()
+            .map(|chosen| match (chosen, templates) {
+                (Some(id), Some(templates)) => format!(" (template: {})", templates.source(*id)),
+                _ => String::new(),
+            })
+            .collect();
 
-                Report::build(ReportKind::Error, &pattern, span.end)
-                    .with_message("Invalid pattern".to_string())
-                    .with_label(Label::new((&pattern, span)).with_message(msg).with_color(a))
-                    .finish()
-                    .eprint((&pattern, Source::from(&pattern)))
-                    .unwrap();
+        (chosen, notes)
+    }
+
+    // The template index, none when templates are turned off or nothing has been indexed
+    fn templates(&self) -> Result<Option<Templates>, IndexBuildError> {
+        if self.args.no_template {
+            return Ok(None);
+        }
+
+        let options = TemplateOptions {
+            template_source_dir: PathBuf::new(),
+            cached_templates_dir: self.config.cache.value.clone(),
+            index_path: self.config.cache.value.join("index"),
+            block_size: 0,
+        };
+        let index = match TemplateIndex::load(options) {
+            Ok(index) => index,
+            Err(IndexBuildError::IoError(_, err)) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(None)
             }
+            Err(err) => return Err(err),
+        };
+        let engine = index.to_engine();
+
+        Ok(Some(Templates { index, engine }))
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            max_outputs: self.config.limit.value as u64,
+            ..Limits::default()
         }
     }
-}
\ No newline at end of file
+
+    // `@file(...)` lists are looked up relative to the pattern file, if any
+    fn parse_options(&self, base_dir: Option<&Path>) -> ParseOptions {
+        let mut options = ParseOptions {
+            limits: self.limits(),
+            ..ParseOptions::default()
+        };
+        if let Some(base_dir) = base_dir {
+            options.resolver = Box::new(FileListResolver::new(base_dir.to_path_buf()));
+        }
+        if self.args.env {
+            options.environment = Some(Box::new(SystemEnvironment));
+            options.strict = self.args.strict_env;
+        }
+
+        options
+    }
+}
+
+// The expanded paths, with the text each group and range produced for the path at the same index
+#[derive(Default)]
+struct Expanded {
+    paths: Vec<String>,
+    captures: Vec<Vec<Option<String>>>,
+}
+
+impl Expanded {
+    // Template variables of a step. `{{1}}` is the text of its first group or range and so on,
+    // `{{path}}`, `{{name}}` and `{{stem}}` are the path, its file name and that without extension
+    fn variables(&self, step: &Step) -> HashMap<String, String> {
+        let path = step.path.as_str();
+        let captures = step
+            .source
+            .and_then(|source| self.captures.get(source))
+            .into_iter()
+            .flatten();
+        let mut variables = captures
+            .enumerate()
+            .filter_map(|(index, capture)| Some(((index + 1).to_string(), capture.clone()?)))
+            .collect::<HashMap<_, _>>();
+
+        let name = path.rsplit('/').next().unwrap_or_default();
+        let stem = Path::new(name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(name);
+        variables.insert("stem".to_string(), stem.to_string());
+        variables.insert("name".to_string(), name.to_string());
+        variables.insert("path".to_string(), path.to_string());
+
+        variables
+    }
+}
+
+// The indexed templates that created files are filled from
+struct Templates {
+    index: TemplateIndex,
+    engine: TemplateEngine,
+}
+
+impl Templates {
+    // The template fitting the name of a file that is written best, preferring `tags`
+    fn choose(&self, step: &Step, tags: &[String]) -> Option<usize> {
+        if step.kind != Kind::File
+            || !matches!(
+                step.action,
+                Action::Create | Action::Rename | Action::Overwrite
+            )
+        {
+            return None;
+        }
+
+        let name = step.path.rsplit('/').next().unwrap_or_default();
+        let tags = tags.iter().map(String::as_str).collect::<Vec<_>>();
+        self.engine.search(name, Some(&tags))
+    }
+
+    // The content of a template with its variables filled in
+    fn render(&self, id: usize, variables: HashMap<String, String>) -> io::Result<String> {
+        let content = self.index.content(id).map_err(io::Error::other)?;
+        Ok(render::render(&content, &variables))
+    }
+
+    fn source(&self, id: usize) -> String {
+        self.index.templates()[id].source().display().to_string()
+    }
+}
+
+// Applies a step, keeping the content it overwrites and writes in the journal. Its entry is
+// added to `entries` as soon as the step changed anything, so a rollback also reverts a step
+// that failed halfway.
+fn apply_recorded(
+    journal: &Journal,
+    root: &Path,
+    step: &Step,
+    content: &[u8],
+    entries: &mut Vec<Entry>,
+) -> io::Result<()> {
+    let target = root.join(&step.target);
+
+    let hash = match (step.kind, step.action) {
+        (Kind::File, Action::Create | Action::Rename | Action::Overwrite) => {
+            Some(journal.store(content)?)
+        }
+        _ => None,
+    };
+    let previous = match step.action {
+        Action::Overwrite => Some(journal.store(&fs::read(&target)?)?),
+        _ => None,
+    };
+    let entry = Entry {
+        path: step.target.clone(),
+        kind: step.kind,
+        existed: !matches!(step.action, Action::Create | Action::Rename),
+        hash,
+        previous,
+    };
+
+    match (step.action, step.kind) {
+        // Recorded once the file exists, before its content is written
+        (Action::Create | Action::Rename, Kind::File) => {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&target)?;
+            entries.push(entry);
+            file.write_all(content)
+        }
+        // Recorded before writing, so the previous content is restored after a failed write
+        (Action::Overwrite, _) => {
+            entries.push(entry);
+            plan::apply(root, step, content)
+        }
+        _ => {
+            plan::apply(root, step, content)?;
+            entries.push(entry);
+            Ok(())
+        }
+    }
+}
+
+// Prints the plan like a diff, '+' is created, '~' overwritten, '>' renamed, '=' left as it is
+// and '!' conflicts
+fn print_plan(plan: &Plan, notes: &[String]) -> ExitCode {
+    for (step, note) in plan.steps.iter().zip(notes) {
+        match step.action {
+            Action::Create => println!("+ {}{}", step.target, note),
+            Action::Exists => println!("= {}", step.path),
+            Action::Skip(reason) => println!("= {}  (skipped, {})", step.path, reason),
+            Action::Overwrite => println!("~ {}{}", step.path, note),
+            Action::Rename => println!("> {} -> {}{}", step.path, step.target, note),
+            Action::Conflict(reason) => println!("! {}  ({})", step.path, reason),
+        }
+    }
+
+    let count = |action| plan.count(Kind::Directory, action) + plan.count(Kind::File, action);
+    println!(
+        "{} directories and {} files to create, {} to overwrite, {} to rename, {} skipped, {} already exist, {} conflict",
+        plan.count(Kind::Directory, Action::Create),
+        plan.count(Kind::File, Action::Create),
+        plan.count(Kind::File, Action::Overwrite),
+        count(Action::Rename),
+        plan.skipped(),
+        count(Action::Exists),
+        plan.conflicts()
+    );
+    match plan.conflicts() {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Commands, PowerFileCli};
+    use clap::Parser;
+
+    fn create_handler(args: &[&str]) -> CreateHandler {
+        let args = ["powerfile", "create"].iter().chain(args);
+        match PowerFileCli::parse_from(args).command {
+            Commands::Create(args) => CreateHandler::new(args, Config::default()),
+            _ => unreachable!(),
+        }
+    }
+
+    // Indexes `content` as the template for `Controller.cs` files in `cache` below `dir`
+    fn build_index(dir: &Path, content: &str) {
+        fs::create_dir_all(dir.join("templates")).unwrap();
+        let template = format!("---\nsuffix: Controller.cs\n---\n{}", content);
+        fs::write(dir.join("templates/controller.cs"), template).unwrap();
+
+        let options = TemplateOptions {
+            template_source_dir: dir.join("templates"),
+            cached_templates_dir: dir.join("cache"),
+            index_path: dir.join("cache/index"),
+            block_size: 128,
+        };
+        TemplateIndex::build(options).write().unwrap();
+    }
+
+    #[test]
+    fn created_files_are_filled_from_the_indexed_template() {
+        let temp = tempfile::tempdir().unwrap();
+        let (root, cache) = (temp.path().join("root"), temp.path().join("cache"));
+        fs::create_dir(&root).unwrap();
+        build_index(temp.path(), "class Controller {}\n");
+
+        let args = [
+            "UserController.cs",
+            "--root",
+            root.to_str().unwrap(),
+            "--cache",
+            cache.to_str().unwrap(),
+        ];
+        let handler = create_handler(&args);
+        let templates = handler.templates().unwrap();
+        let plan = Plan::new(
+            &root,
+            &["UserController.cs", "notes.txt"],
+            ConflictPolicy::Error,
+        );
+        let (_, notes) = handler.choose_templates(&plan, templates.as_ref());
+        let source = temp.path().join("templates/controller.cs");
+        assert_eq!(
+            notes,
+            [format!(" (template: {})", source.display()), String::new()]
+        );
+
+        assert_eq!(handler.handle(), ExitCode::SUCCESS);
+        assert_eq!(
+            fs::read_to_string(root.join("UserController.cs")).unwrap(),
+            "class Controller {}\n"
+        );
+
+        let args = [
+            "Empty(Controller).cs",
+            "--root",
+            root.to_str().unwrap(),
+            "--cache",
+            cache.to_str().unwrap(),
+            "--no-template",
+        ];
+        assert_eq!(create_handler(&args).handle(), ExitCode::SUCCESS);
+        assert_eq!(
+            fs::read_to_string(root.join("EmptyController.cs")).unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn templates_are_rendered_with_the_captures_and_name_of_each_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let (root, cache) = (temp.path().join("root"), temp.path().join("cache"));
+        fs::create_dir(&root).unwrap();
+        build_index(
+            temp.path(),
+            "// {{path}}\nclass {{stem}} : Controller<{{1}}> {}\n",
+        );
+
+        let args = [
+            "api/(User,Order)Controller.cs",
+            "--root",
+            root.to_str().unwrap(),
+            "--cache",
+            cache.to_str().unwrap(),
+        ];
+        assert_eq!(create_handler(&args).handle(), ExitCode::SUCCESS);
+        assert_eq!(
+            fs::read_to_string(root.join("api/UserController.cs")).unwrap(),
+            "// api/UserController.cs\nclass UserController : Controller<User> {}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("api/OrderController.cs")).unwrap(),
+            "// api/OrderController.cs\nclass OrderController : Controller<Order> {}\n"
+        );
+    }
+
+    #[test]
+    fn a_path_expanded_twice_keeps_the_captures_it_was_planned_with() {
+        let temp = tempfile::tempdir().unwrap();
+        let (root, cache) = (temp.path().join("root"), temp.path().join("cache"));
+        fs::create_dir(&root).unwrap();
+        build_index(temp.path(), "{{1}}+{{2}}");
+
+        // `ABController.cs` is expanded from both `A` and `B`, and from `AB` and nothing
+        let args = [
+            "(A,AB)(B,)Controller.cs",
+            "--root",
+            root.to_str().unwrap(),
+            "--cache",
+            cache.to_str().unwrap(),
+        ];
+        assert_eq!(create_handler(&args).handle(), ExitCode::SUCCESS);
+        assert_eq!(
+            fs::read_to_string(root.join("ABController.cs")).unwrap(),
+            "A+B"
+        );
+    }
+
+    // Every path below `dir` with the content of the files
+    fn snapshot(dir: &Path) -> Vec<(PathBuf, Option<Vec<u8>>)> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            match path.is_dir() {
+                true => {
+                    paths.push((path.clone(), None));
+                    paths.extend(snapshot(&path));
+                }
+                false => paths.push((path.clone(), Some(fs::read(&path).unwrap()))),
+            }
+        }
+
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn a_failed_create_leaves_the_root_as_it_was() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::write(root.join("keep.txt"), "old").unwrap();
+        let before = snapshot(root);
+
+        // The last name is too long for the filesystem
+        let pattern = format!("(keep.txt,a.txt,dir/b.txt,{})", "x".repeat(300));
+        let args = [
+            &pattern,
+            "--root",
+            root.to_str().unwrap(),
+            "--on-conflict",
+            "overwrite",
+            "--no-template",
+        ];
+        assert_eq!(create_handler(&args).handle(), ExitCode::FAILURE);
+        assert_eq!(snapshot(root), before);
+    }
+
+    #[test]
+    fn only_a_missing_index_means_no_templates() {
+        let temp = tempfile::tempdir().unwrap();
+        let (root, cache) = (temp.path().join("root"), temp.path().join("cache"));
+        fs::create_dir(&root).unwrap();
+        let args = [
+            "a.txt",
+            "--root",
+            root.to_str().unwrap(),
+            "--cache",
+            cache.to_str().unwrap(),
+        ];
+
+        assert!(create_handler(&args).templates().unwrap().is_none());
+
+        fs::create_dir(&cache).unwrap();
+        fs::write(cache.join("index.manifest"), "not an index").unwrap();
+        assert!(create_handler(&args).templates().is_err());
+        assert_eq!(create_handler(&args).handle(), ExitCode::FAILURE);
+        assert!(!root.join("a.txt").exists());
+    }
+}
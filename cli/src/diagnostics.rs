@@ -0,0 +1,72 @@
+use ariadne::{ColorGenerator, Label, Report, ReportKind, Source};
+use powerfile_core::include::Sources;
+use powerfile_core::pattern_file::Diagnostic;
+use std::ops::Range;
+
+/// Prints every error against the source it was found in
+pub fn report(name: &str, source: &str, errors: &[(String, Range<usize>)]) {
+    let mut colors = ColorGenerator::new();
+
+    for (msg, span) in errors {
+        Report::build(ReportKind::Error, name, span.end)
+            .with_message("Invalid pattern".to_string())
+            .with_label(
+                Label::new((name, span.clone()))
+                    .with_message(msg)
+                    .with_color(colors.next()),
+            )
+            .finish()
+            .eprint((name, Source::from(source)))
+            .unwrap();
+    }
+}
+
+/// Prints pattern file diagnostics, noting the include lines that led to the failing file
+pub fn report_sources(sources: &Sources, diagnostics: &[Diagnostic]) {
+    print_sources(sources, diagnostics, ReportKind::Error, "Invalid pattern");
+}
+
+/// Prints pattern file warnings like [`report_sources`]
+pub fn warn_sources(sources: &Sources, diagnostics: &[Diagnostic]) {
+    print_sources(
+        sources,
+        diagnostics,
+        ReportKind::Warning,
+        "Suspicious pattern",
+    );
+}
+
+fn print_sources(sources: &Sources, diagnostics: &[Diagnostic], kind: ReportKind, title: &str) {
+    let mut colors = ColorGenerator::new();
+
+    for diagnostic in diagnostics {
+        let file = &sources.files()[diagnostic.file];
+        let name = file.path.to_string_lossy();
+
+        let mut report = Report::build(kind, name.as_ref(), diagnostic.span.end)
+            .with_message(title.to_string())
+            .with_label(
+                Label::new((name.as_ref(), diagnostic.span.clone()))
+                    .with_message(&diagnostic.message)
+                    .with_color(colors.next()),
+            );
+
+        let chain = sources
+            .include_chain(diagnostic.file)
+            .into_iter()
+            .map(|(parent, span)| {
+                let parent = &sources.files()[parent];
+                let line = parent.source[..span.start].matches('\n').count() + 1;
+                format!("{}:{}", parent.path.display(), line)
+            })
+            .collect::<Vec<_>>();
+        if !chain.is_empty() {
+            report = report.with_note(format!("included from {}", chain.join(" <- ")));
+        }
+
+        report
+            .finish()
+            .eprint((name.as_ref(), Source::from(&file.source)))
+            .unwrap();
+    }
+}
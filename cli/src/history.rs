@@ -0,0 +1,248 @@
+use crate::journal::{self, Journal};
+use crate::{HistoryArgs, UndoArgs};
+use std::path::Path;
+use std::process::ExitCode;
+
+pub struct UndoHandler {
+    args: UndoArgs,
+}
+
+impl UndoHandler {
+    pub fn new(args: UndoArgs) -> Self {
+        UndoHandler { args }
+    }
+
+    /// Undoes the last applied operation, refusing when any of its files were modified since
+    pub fn handle(&self) -> ExitCode {
+        let Some(mut journal) = open_journal(&self.args.root) else {
+            return ExitCode::FAILURE;
+        };
+        let Some(operation) = journal
+            .applied
+            .checked_sub(1)
+            .map(|index| &journal.operations[index])
+        else {
+            println!("Nothing to undo");
+            return ExitCode::SUCCESS;
+        };
+
+        let problems = operation.undo_problems();
+        if !problems.is_empty() {
+            for problem in &problems {
+                eprintln!("{}", problem);
+            }
+            eprintln!(
+                "Refusing to undo operation {}, nothing was removed",
+                operation.id
+            );
+            return ExitCode::FAILURE;
+        }
+
+        if self.args.dry_run {
+            println!(
+                "Would undo operation {}: {}",
+                operation.id, operation.command
+            );
+            for entry in operation
+                .entries
+                .iter()
+                .rev()
+                .filter(|entry| !entry.existed)
+            {
+                println!("- {}", entry.path);
+            }
+            for entry in operation
+                .entries
+                .iter()
+                .filter(|entry| entry.previous.is_some())
+            {
+                println!("~ {}", entry.path);
+            }
+            return ExitCode::SUCCESS;
+        }
+
+        let failures = operation.undo(&journal);
+        if !failures.is_empty() {
+            for failure in &failures {
+                eprintln!("{}", failure);
+            }
+            eprintln!("Failed to undo operation {} completely", operation.id);
+            return ExitCode::FAILURE;
+        }
+        println!(
+            "Undid operation {}, {} path(s) reverted",
+            operation.id,
+            operation.changes()
+        );
+
+        journal.applied -= 1;
+        save_journal(&journal)
+    }
+}
+
+pub struct RedoHandler {
+    args: UndoArgs,
+}
+
+impl RedoHandler {
+    pub fn new(args: UndoArgs) -> Self {
+        RedoHandler { args }
+    }
+
+    /// Applies the last undone operation again, refusing when its paths were changed since
+    pub fn handle(&self) -> ExitCode {
+        let Some(mut journal) = open_journal(&self.args.root) else {
+            return ExitCode::FAILURE;
+        };
+        let Some(operation) = journal.operations.get(journal.applied) else {
+            println!("Nothing to redo");
+            return ExitCode::SUCCESS;
+        };
+
+        let problems = operation.redo_problems();
+        if !problems.is_empty() {
+            for problem in &problems {
+                eprintln!("{}", problem);
+            }
+            eprintln!(
+                "Refusing to redo operation {}, nothing was created",
+                operation.id
+            );
+            return ExitCode::FAILURE;
+        }
+
+        if self.args.dry_run {
+            println!(
+                "Would redo operation {}: {}",
+                operation.id, operation.command
+            );
+            for entry in operation.entries.iter().filter(|entry| !entry.existed) {
+                println!("+ {}", entry.path);
+            }
+            for entry in operation
+                .entries
+                .iter()
+                .filter(|entry| entry.previous.is_some())
+            {
+                println!("~ {}", entry.path);
+            }
+            return ExitCode::SUCCESS;
+        }
+
+        if let Err(err) = operation.redo(&journal) {
+            eprintln!("Failed to redo operation {}: {}", operation.id, err);
+            return ExitCode::FAILURE;
+        }
+        println!(
+            "Redid operation {}, {} path(s) restored",
+            operation.id,
+            operation.changes()
+        );
+
+        journal.applied += 1;
+        save_journal(&journal)
+    }
+}
+
+pub struct HistoryHandler {
+    args: HistoryArgs,
+}
+
+impl HistoryHandler {
+    pub fn new(args: HistoryArgs) -> Self {
+        HistoryHandler { args }
+    }
+
+    /// Lists the operations in the journal, newest first
+    pub fn handle(&self) -> ExitCode {
+        let Some(journal) = open_journal(&self.args.root) else {
+            return ExitCode::FAILURE;
+        };
+        if journal.operations.is_empty() {
+            println!("No operations recorded");
+            return ExitCode::SUCCESS;
+        }
+
+        let count = self.args.count.unwrap_or(usize::MAX);
+        for (index, operation) in journal.operations.iter().enumerate().rev().take(count) {
+            let status = match index < journal.applied {
+                true => "applied",
+                false => "undone",
+            };
+            println!(
+                "{:>4}  {}  {:<7}  {:>4} path(s)  {}",
+                operation.id,
+                format_time(operation.time),
+                status,
+                operation.changes(),
+                operation.command
+            );
+        }
+
+        ExitCode::SUCCESS
+    }
+}
+
+// The journal kept below the root `create` was run in
+fn open_journal(root: &Path) -> Option<Journal> {
+    let dir = root.join(journal::DIRECTORY);
+    match Journal::open(&dir) {
+        Ok(journal) => Some(journal),
+        Err(err) => {
+            eprintln!("Failed to read the journal in '{}': {}", dir.display(), err);
+            None
+        }
+    }
+}
+
+fn save_journal(journal: &Journal) -> ExitCode {
+    match journal.save() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!(
+                "Failed to write the journal in '{}': {}",
+                journal.dir().display(),
+                err
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Seconds since the Unix epoch as a UTC date and time
+fn format_time(time: u64) -> String {
+    let (days, seconds) = (time / 86400, time % 86400);
+
+    // Days to a civil date, counting in 400 year eras starting on March 1st
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_time_gives_utc_dates() {
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(951782400), "2000-02-29 00:00");
+        assert_eq!(format_time(1792329300), "2026-10-18 13:15");
+    }
+}
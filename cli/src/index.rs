@@ -0,0 +1,188 @@
+use crate::config::Config;
+use crate::{IndexArgs, IndexCommand};
+use powerfile_templating::index::{CachedTemplate, TemplateIndex, TemplateOptions};
+use std::process::ExitCode;
+
+pub struct IndexHandler {
+    args: IndexArgs,
+    config: Config,
+}
+
+impl IndexHandler {
+    pub fn new(args: IndexArgs, mut config: Config) -> Self {
+        config.templates.flag(args.templates.clone());
+        config.cache.flag(args.cache.clone());
+        config.block_size.flag(args.block_size);
+
+        IndexHandler { args, config }
+    }
+
+    pub fn handle(&self) -> ExitCode {
+        match &self.args.command {
+            IndexCommand::Build => self.build(),
+            IndexCommand::Rebuild => match self.clean() {
+                ExitCode::SUCCESS => self.build(),
+                failure => failure,
+            },
+            IndexCommand::List => self.list(),
+            IndexCommand::Show { id } => self.show(*id),
+            IndexCommand::Search { name, tags } => self.search(name, tags),
+            IndexCommand::Clean => self.clean(),
+        }
+    }
+
+    // Writes the templates that could be indexed, failing when any could not
+    fn build(&self) -> ExitCode {
+        let options = self.options();
+        if options.index_path.exists() || options.manifest_path().exists() {
+            eprintln!(
+                "An index already exists in '{}', use `index rebuild` to replace it",
+                self.config.cache.value.display()
+            );
+            return ExitCode::FAILURE;
+        }
+
+        let index = TemplateIndex::build(options);
+        if let Err(err) = index.write() {
+            eprintln!("Failed to write the index: {}", err);
+            return ExitCode::FAILURE;
+        }
+
+        for err in index.errors() {
+            eprintln!("failed    {}", err);
+        }
+        println!(
+            "Indexed {} template(s) from '{}', {} failed",
+            index.templates().len(),
+            self.config.templates.value.display(),
+            index.errors().len()
+        );
+
+        match index.errors().len() {
+            0 => ExitCode::SUCCESS,
+            _ => ExitCode::FAILURE,
+        }
+    }
+
+    fn list(&self) -> ExitCode {
+        let Some(index) = self.load() else {
+            return ExitCode::FAILURE;
+        };
+
+        if index.templates().is_empty() {
+            println!("No templates indexed");
+        }
+        for (id, template) in index.templates().iter().enumerate() {
+            println!(
+                "{:>4}  {}  {}",
+                id,
+                template.source().display(),
+                summary(template)
+            );
+        }
+
+        ExitCode::SUCCESS
+    }
+
+    fn show(&self, id: usize) -> ExitCode {
+        let Some(index) = self.load() else {
+            return ExitCode::FAILURE;
+        };
+
+        let content = match index.content(id) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let template = &index.templates()[id];
+        let metadata = template.metadata();
+        println!("source:   {}", template.source().display());
+        println!("cached:   {}", template.path().display());
+        println!("prefixes: {}", metadata.prefixes().join(" "));
+        println!("suffixes: {}", metadata.suffixes().join(" "));
+        println!("tags:     {}", metadata.tags().join(" "));
+        println!("---");
+        print!("{}", content);
+
+        ExitCode::SUCCESS
+    }
+
+    fn search(&self, name: &str, tags: &[String]) -> ExitCode {
+        let Some(index) = self.load() else {
+            return ExitCode::FAILURE;
+        };
+
+        let tags = tags.iter().map(String::as_str).collect::<Vec<_>>();
+        match index.to_engine().search(name, Some(&tags)) {
+            Some(id) => {
+                let template = &index.templates()[id];
+                println!(
+                    "{:>4}  {}  {}",
+                    id,
+                    template.source().display(),
+                    summary(template)
+                );
+                ExitCode::SUCCESS
+            }
+            None => {
+                eprintln!("No template matches '{}'", name);
+                ExitCode::FAILURE
+            }
+        }
+    }
+
+    fn clean(&self) -> ExitCode {
+        match TemplateIndex::clean(&self.options()) {
+            Ok(removed) => {
+                println!(
+                    "Removed {} file(s) from '{}'",
+                    removed,
+                    self.config.cache.value.display()
+                );
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("Failed to clean the index: {}", err);
+                ExitCode::FAILURE
+            }
+        }
+    }
+
+    fn load(&self) -> Option<TemplateIndex> {
+        match TemplateIndex::load(self.options()) {
+            Ok(index) => Some(index),
+            Err(err) => {
+                eprintln!("Failed to read the index, run `index build` first: {}", err);
+                None
+            }
+        }
+    }
+
+    fn options(&self) -> TemplateOptions {
+        TemplateOptions {
+            template_source_dir: self.config.templates.value.clone(),
+            cached_templates_dir: self.config.cache.value.clone(),
+            index_path: self.config.cache.value.join("index"),
+            block_size: self.config.block_size.value,
+        }
+    }
+}
+
+// What a template is found by, e.g. `prefix: I  suffix: Handler.cs  tags: csharp`
+fn summary(template: &CachedTemplate) -> String {
+    let metadata = template.metadata();
+
+    [
+        ("prefix", metadata.prefixes()),
+        ("suffix", metadata.suffixes()),
+        ("tags", metadata.tags()),
+    ]
+    .into_iter()
+    .filter(|(_, values)| !values.is_empty())
+    .map(|(name, values)| format!("{}: {}", name, values.join(" ")))
+    .collect::<Vec<_>>()
+    .join("  ")
+}
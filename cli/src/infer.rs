@@ -0,0 +1,73 @@
+use crate::InferArgs;
+use powerfile_core::infer;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process::ExitCode;
+
+pub struct InferHandler {
+    args: InferArgs,
+}
+
+impl InferHandler {
+    pub fn new(args: InferArgs) -> Self {
+        InferHandler { args }
+    }
+
+    pub fn handle(&self) -> ExitCode {
+        let paths = match &self.args.dir {
+            Some(dir) => walk(dir, self.args.hidden),
+            None => io::stdin().lock().lines().collect(),
+        };
+
+        let paths = match paths {
+            Ok(paths) => paths,
+            Err(err) => {
+                eprintln!("Failed to read paths: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        match infer::infer(&paths) {
+            Ok(pattern) => {
+                println!("{}", pattern);
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+// Files relative to `root`, directories only show up when they are empty
+fn walk(root: &Path, hidden: bool) -> io::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    let mut pending = vec![String::new()];
+
+    while let Some(dir) = pending.pop() {
+        let mut empty = true;
+
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !hidden && name.starts_with('.') {
+                continue;
+            }
+
+            empty = false;
+            let path = format!("{}{}", dir, name);
+            match entry.file_type()?.is_dir() {
+                true => pending.push(format!("{}/", path)),
+                false => paths.push(path),
+            }
+        }
+
+        if empty && !dir.is_empty() {
+            paths.push(dir);
+        }
+    }
+
+    Ok(paths)
+}
@@ -0,0 +1,391 @@
+use crate::plan::Kind;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The directory below the root of `create` that keeps the journal
+pub const DIRECTORY: &str = ".powerfile";
+
+/// A path touched by an operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Relative to the root of the operation, directories end with '/'
+    pub path: String,
+    pub kind: Kind,
+    /// Whether the path was there before the operation
+    pub existed: bool,
+    /// The hash of the content written to a file
+    pub hash: Option<String>,
+    /// The hash of the content of an overwritten file
+    pub previous: Option<String>,
+}
+
+impl Entry {
+    // Whether undo has anything to do with the entry
+    fn changed(&self) -> bool {
+        !self.existed || self.previous.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: u64,
+    /// Seconds since the Unix epoch
+    pub time: u64,
+    /// The arguments it was run with
+    pub command: String,
+    pub root: PathBuf,
+    pub entries: Vec<Entry>,
+}
+
+impl Operation {
+    /// The number of paths created or overwritten
+    pub fn changes(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.changed()).count()
+    }
+
+    /// Why the operation can not be undone, empty when it can
+    pub fn undo_problems(&self) -> Vec<String> {
+        let created = self
+            .entries
+            .iter()
+            .filter(|entry| !entry.existed)
+            .map(|entry| self.root.join(entry.path.trim_end_matches('/')))
+            .collect::<HashSet<_>>();
+
+        let mut problems = Vec::new();
+        for entry in self.entries.iter().filter(|entry| entry.changed()) {
+            let target = self.root.join(&entry.path);
+
+            match entry.kind {
+                Kind::File => match fs::read(&target) {
+                    Ok(content) if Some(hash(&content)) != entry.hash => {
+                        problems.push(format!("'{}' was modified since", entry.path))
+                    }
+                    Ok(_) => {}
+                    Err(err) if err.kind() == io::ErrorKind::NotFound && !entry.existed => {}
+                    Err(err) => problems.push(format!("'{}': {}", entry.path, err)),
+                },
+                Kind::Directory => match fs::read_dir(&target) {
+                    Ok(children) => {
+                        let added = children
+                            .filter_map(|child| child.ok())
+                            .filter(|child| !created.contains(&child.path()))
+                            .count();
+                        if added > 0 {
+                            problems
+                                .push(format!("'{}' has {} new path(s) in it", entry.path, added));
+                        }
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => problems.push(format!("'{}': {}", entry.path, err)),
+                },
+            }
+        }
+
+        problems
+    }
+
+    /// Removes what the operation created and restores what it overwrote, see [`revert`]
+    pub fn undo(&self, journal: &Journal) -> Vec<String> {
+        revert(journal, &self.root, &self.entries)
+    }
+
+    /// Why the operation can not be applied again, empty when it can
+    pub fn redo_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for entry in self.entries.iter().filter(|entry| entry.changed()) {
+            let target = self.root.join(&entry.path);
+
+            match (&entry.previous, entry.kind) {
+                (Some(previous), _) => match fs::read(&target) {
+                    Ok(content) if hash(&content) == *previous => {}
+                    Ok(_) => problems.push(format!("'{}' was modified since", entry.path)),
+                    Err(err) => problems.push(format!("'{}': {}", entry.path, err)),
+                },
+                (None, Kind::File) if target.exists() => {
+                    problems.push(format!("'{}' exists again", entry.path))
+                }
+                (None, Kind::Directory) if target.exists() && !target.is_dir() => {
+                    problems.push(format!("'{}' is a file now", entry.path))
+                }
+                (None, _) => {}
+            }
+        }
+
+        problems
+    }
+
+    /// Creates and overwrites the paths again with the content they had, first entry first
+    pub fn redo(&self, journal: &Journal) -> io::Result<()> {
+        for entry in self.entries.iter().filter(|entry| entry.changed()) {
+            let target = self.root.join(&entry.path);
+            let content = match &entry.hash {
+                Some(hash) => journal.load(hash)?,
+                None => Vec::new(),
+            };
+
+            match (&entry.previous, entry.kind) {
+                (Some(_), _) => fs::write(&target, content)?,
+                (None, Kind::File) => OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&target)?
+                    .write_all(&content)?,
+                (None, Kind::Directory) => match fs::create_dir(&target) {
+                    Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err),
+                    _ => {}
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The operations run in a directory, with the content of the files they wrote and overwrote
+/// kept by hash so they can be restored
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(skip)]
+    dir: PathBuf,
+    pub operations: Vec<Operation>,
+    /// How many operations are applied, the ones after them have been undone
+    pub applied: usize,
+}
+
+impl Journal {
+    /// Opens the journal kept in `dir`, which is empty when there is none yet
+    pub fn open(dir: &Path) -> io::Result<Journal> {
+        let mut journal = match fs::read_to_string(dir.join("journal.json")) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Journal::default(),
+            Err(err) => return Err(err),
+        };
+        journal.dir = dir.to_path_buf();
+
+        Ok(journal)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        // Written next to the journal first, so it is never left half written
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let temporary = self.dir.join("journal.json.tmp");
+        fs::write(&temporary, json)?;
+        fs::rename(temporary, self.dir.join("journal.json"))?;
+
+        self.remove_unused()
+    }
+
+    /// Removes the content no operation refers to anymore, such as that of undone operations
+    /// that were replaced or of a run that was rolled back
+    pub fn remove_unused(&self) -> io::Result<()> {
+        let used = self
+            .operations
+            .iter()
+            .flat_map(|operation| &operation.entries)
+            .flat_map(|entry| [&entry.hash, &entry.previous])
+            .flatten()
+            .collect::<HashSet<_>>();
+
+        let objects = match fs::read_dir(self.dir.join("objects")) {
+            Ok(objects) => objects,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for object in objects {
+            let object = object?;
+            if !used.contains(&object.file_name().to_string_lossy().to_string()) {
+                fs::remove_file(object.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keeps `content` until the journal is removed, returning its hash
+    pub fn store(&self, content: &[u8]) -> io::Result<String> {
+        let hash = hash(content);
+        let path = self.dir.join("objects").join(&hash);
+
+        if !path.exists() {
+            fs::create_dir_all(self.dir.join("objects"))?;
+            fs::write(path, content)?;
+        }
+
+        Ok(hash)
+    }
+
+    pub fn load(&self, hash: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.dir.join("objects").join(hash))
+    }
+
+    /// Adds an operation that was just applied, the undone ones can no longer be redone
+    pub fn record(&mut self, command: String, root: PathBuf, entries: Vec<Entry>) {
+        self.operations.truncate(self.applied);
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        self.operations.push(Operation {
+            id: self
+                .operations
+                .last()
+                .map_or(1, |operation| operation.id + 1),
+            time,
+            command,
+            root,
+            entries,
+        });
+        self.applied = self.operations.len();
+    }
+}
+
+/// Removes the created paths of `entries` below `root` and restores the files they overwrote,
+/// last entry first.
+///
+/// Goes on after an error so as much as possible is reverted, returning what could not be.
+pub fn revert(journal: &Journal, root: &Path, entries: &[Entry]) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    for entry in entries.iter().rev().filter(|entry| entry.changed()) {
+        let target = root.join(&entry.path);
+
+        let result = match (&entry.previous, entry.kind) {
+            (Some(previous), _) => journal
+                .load(previous)
+                .and_then(|content| fs::write(&target, content)),
+            (None, Kind::File) => fs::remove_file(&target),
+            (None, Kind::Directory) => fs::remove_dir(&target),
+        };
+        match result {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                failures.push(format!("'{}': {}", entry.path, err))
+            }
+            _ => {}
+        }
+    }
+
+    failures
+}
+
+/// The SHA-256 of `content` in hex
+pub fn hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_refuses_modified_files_and_redo_restores_content() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("a/b.txt"), "new").unwrap();
+        fs::write(root.join("c.txt"), "written").unwrap();
+
+        let journal = Journal::open(&root.join(DIRECTORY)).unwrap();
+        let entry = |path: &str, kind, hash: Option<&str>, previous: Option<&str>| Entry {
+            path: path.to_string(),
+            kind,
+            existed: previous.is_some(),
+            hash: hash.map(|content| journal.store(content.as_bytes()).unwrap()),
+            previous: previous.map(|content| journal.store(content.as_bytes()).unwrap()),
+        };
+        let operation = Operation {
+            id: 1,
+            time: 0,
+            command: String::new(),
+            root: root.to_path_buf(),
+            entries: vec![
+                entry("a/", Kind::Directory, None, None),
+                entry("a/b.txt", Kind::File, Some("new"), None),
+                entry("c.txt", Kind::File, Some("written"), Some("old")),
+            ],
+        };
+
+        fs::write(root.join("a/b.txt"), "edited").unwrap();
+        assert_eq!(operation.undo_problems(), ["'a/b.txt' was modified since"]);
+        fs::write(root.join("a/b.txt"), "new").unwrap();
+        assert!(operation.undo_problems().is_empty());
+
+        assert!(operation.undo(&journal).is_empty());
+        assert!(!root.join("a").exists());
+        assert_eq!(fs::read_to_string(root.join("c.txt")).unwrap(), "old");
+
+        assert!(operation.redo_problems().is_empty());
+        operation.redo(&journal).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a/b.txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(root.join("c.txt")).unwrap(), "written");
+        assert_eq!(
+            operation.redo_problems(),
+            ["'a/b.txt' exists again", "'c.txt' was modified since"]
+        );
+    }
+
+    #[test]
+    fn save_removes_content_no_operation_refers_to() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut journal = Journal::open(&temp.path().join(DIRECTORY)).unwrap();
+
+        let kept = journal.store(b"kept").unwrap();
+        let unused = journal.store(b"unused").unwrap();
+        let entry = Entry {
+            path: "a.txt".to_string(),
+            kind: Kind::File,
+            existed: false,
+            hash: Some(kept.clone()),
+            previous: None,
+        };
+        journal.record(String::new(), temp.path().to_path_buf(), vec![entry]);
+        journal.save().unwrap();
+
+        assert_eq!(journal.load(&kept).unwrap(), b"kept");
+        assert!(journal.load(&unused).is_err());
+    }
+
+    #[test]
+    fn revert_goes_on_after_a_failure() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("a/kept")).unwrap();
+        fs::write(root.join("b.txt"), "").unwrap();
+
+        let journal = Journal::open(&root.join(DIRECTORY)).unwrap();
+        let created = |path: &str, kind| Entry {
+            path: path.to_string(),
+            kind,
+            existed: false,
+            hash: None,
+            previous: None,
+        };
+        let entries = [
+            created("a/", Kind::Directory),
+            created("b.txt", Kind::File),
+            created("c.txt", Kind::File),
+        ];
+
+        let failures = revert(&journal, root, &entries);
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("'a/': "));
+        assert!(!root.join("b.txt").exists());
+    }
+}
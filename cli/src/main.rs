@@ -1,7 +1,31 @@
+mod config;
+mod convert;
 mod create;
+mod diagnostics;
+mod history;
+mod index;
+mod infer;
+mod journal;
+mod matching;
+mod parse;
+mod plan;
+mod preview;
+mod rename;
 
-use clap::{Args, Parser, Subcommand};
-use powerfile_core::interpreter::Interpreter;
+use crate::config::{Config, ConfigHandler};
+use crate::convert::ConvertHandler;
+use crate::create::CreateHandler;
+use crate::history::{HistoryHandler, RedoHandler, UndoHandler};
+use crate::index::IndexHandler;
+use crate::infer::InferHandler;
+use crate::matching::MatchHandler;
+use crate::parse::ParseHandler;
+use crate::preview::PreviewHandler;
+use crate::rename::RenameHandler;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
 #[derive(Parser)]
 #[command(name = "PowerFile")]
@@ -17,47 +41,261 @@ pub struct PowerFileCli {
 enum Commands {
     /// Create files and directories from a pattern
     Create(CreateArgs),
-    /// Preview your pattern
+    /// Remove what the last `create` created
+    Undo(UndoArgs),
+    /// Apply the last undone `create` again
+    Redo(UndoArgs),
+    /// List the recorded `create` operations
+    History(HistoryArgs),
+    /// Show the paths of a pattern as a tree, marking the ones that already exist
     Preview(PreviewArgs),
     /// Manage your template index
     Index(IndexArgs),
+    /// Check whether paths can be produced by a pattern
+    Match(MatchArgs),
+    /// Move paths matching a pattern, reusing its groups in the destination
+    Rename(RenameArgs),
+    /// Write a pattern producing the files in a directory, or the paths read from stdin
+    Infer(InferArgs),
+    /// Convert between bash brace expansion and patterns
+    Convert(ConvertArgs),
+    /// Print the syntax tree of a pattern
+    Parse(ParseArgs),
+    /// Inspect the configuration
+    Config(ConfigArgs),
 }
 
 #[derive(Args)]
 struct CreateArgs {
-    pattern: String,
-    #[arg(default_value_t = 100)]
-    limit: u32,
+    #[arg(required_unless_present = "file")]
+    pattern: Option<String>,
+    /// Read the patterns from a `.pf` pattern file instead
+    #[arg(short, long, conflicts_with = "pattern")]
+    file: Option<PathBuf>,
+    /// Directory to search for `@include(...)` files, may be repeated
+    #[arg(short = 'I', long, requires = "file")]
+    include_dir: Vec<PathBuf>,
+    /// Maximum number of paths to create [default: 100, see `config show`]
+    limit: Option<u32>,
+    /// Print the syntax tree of the patterns
     #[arg(short, long)]
     debug: bool,
+    /// Directory the paths are created in
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
+    /// Expand `$VAR`, `${VAR}` and a leading `~` from the environment
+    #[arg(long)]
+    env: bool,
+    /// Fail when an interpolated variable is not set
+    #[arg(long, requires = "env")]
+    strict_env: bool,
+    /// Only print what would be created, compared against the filesystem
+    #[arg(long)]
+    dry_run: bool,
+    /// What to do with paths that already exist or are in the way [default: skip]
+    #[arg(long, value_enum)]
+    on_conflict: Option<ConflictPolicy>,
+    /// Prefer templates with these tags for the created files, may be repeated
+    #[arg(long, value_delimiter = ',')]
     tags: Vec<String>,
+    /// Create empty files instead of filling them from a template
+    #[arg(long)]
+    no_template: bool,
+    /// Directory of the template index, see `index build` [default: cache]
+    #[arg(long)]
+    cache: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ConflictPolicy {
+    /// Leave the existing path as it is
+    Skip,
+    /// Replace existing files, a directory in the way is still a conflict
+    Overwrite,
+    /// Create the path with a numeric suffix, e.g. `notes_1.txt`
+    Rename,
+    /// Create nothing when any path conflicts
+    Error,
+    /// Ask for every conflicting path before creating anything
+    Prompt,
 }
 
 #[derive(Args)]
-struct PreviewArgs {
+struct MatchArgs {
+    pattern: String,
+    #[arg(required = true)]
+    paths: Vec<String>,
+}
 
+#[derive(Args)]
+struct RenameArgs {
+    /// Pattern selecting the paths to move, e.g. `src/(a,b)_(old).rs`
+    source: String,
+    /// Destination with `{n}` referring to the n-th group of the source, e.g. `src/{1}/{2}.rs`
+    destination: String,
+    /// Directory the paths are relative to
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
+    /// Only print the planned moves
+    #[arg(long)]
+    dry_run: bool,
 }
 
 #[derive(Args)]
-struct IndexArgs {
+struct InferArgs {
+    dir: Option<PathBuf>,
+    /// Include files and directories starting with '.'
+    #[arg(long)]
+    hidden: bool,
+}
 
+#[derive(Args)]
+struct UndoArgs {
+    /// Only print what would change
+    #[arg(long)]
+    dry_run: bool,
+    /// Directory `create` was run in, the journal is kept below it
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
 }
 
-fn main() {
-    //let args = Args::parse();
+#[derive(Args)]
+struct HistoryArgs {
+    /// Only list the most recent operations
+    #[arg(short = 'n', long)]
+    count: Option<usize>,
+    /// Directory `create` was run in, the journal is kept below it
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
+}
 
-    // TODO: Implement UNDO/REDO?
+#[derive(Args)]
+struct ConvertArgs {
+    input: String,
+    /// Syntax of the input, it is converted to the other one
+    #[arg(long, value_enum, default_value_t = Syntax::Bash)]
+    from: Syntax,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Syntax {
+    /// Bash brace expansion, e.g. `src/{a,b}_{1..3}.rs`
+    Bash,
+    /// A pattern, e.g. `src/(a,b)_[1..3].rs`
+    Powerfile,
+}
+
+#[derive(Args)]
+struct ParseArgs {
+    #[arg(required_unless_present = "file")]
+    pattern: Option<String>,
+    /// Read the patterns from a `.pf` pattern file instead
+    #[arg(short, long, conflicts_with = "pattern")]
+    file: Option<PathBuf>,
+    /// Directory to search for `@include(...)` files, may be repeated
+    #[arg(short = 'I', long, requires = "file")]
+    include_dir: Vec<PathBuf>,
+    /// Print the syntax tree and any errors as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print every setting with where its value came from
+    Show,
+}
+
+#[derive(Args)]
+struct PreviewArgs {
+    pattern: String,
+    /// Directory the paths are compared against
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
+    /// Only show entries up to this many levels deep
+    #[arg(long)]
+    depth: Option<usize>,
+    /// Collapse siblings into a pattern when a directory has more entries than this
+    #[arg(long, default_value_t = 20)]
+    max_entries: usize,
+    /// Maximum number of paths to expand
+    #[arg(long, default_value_t = 10_000)]
+    limit: u64,
+}
+
+#[derive(Args)]
+struct IndexArgs {
+    #[command(subcommand)]
+    command: IndexCommand,
+    /// Directory the templates are read from [default: templates]
+    #[arg(long, global = true)]
+    templates: Option<PathBuf>,
+    /// Directory the index and the cached templates are kept in [default: cache]
+    #[arg(long, global = true)]
+    cache: Option<PathBuf>,
+    /// Bytes reserved for the path of each cached template in the index [default: 128]
+    #[arg(long, global = true)]
+    block_size: Option<usize>,
+}
+
+#[derive(Subcommand)]
+enum IndexCommand {
+    /// Index the templates, unless there already is an index
+    Build,
+    /// Remove the index and build it again
+    Rebuild,
+    /// List the indexed templates
+    List,
+    /// Print the metadata and content of a template
+    Show { id: usize },
+    /// Find the template that fits a file name best
+    Search {
+        name: String,
+        /// Prefer templates with this tag, may be repeated
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Remove the index and the cached templates
+    Clean,
+}
+
+fn main() -> ExitCode {
     //let pattern = "(Environments/(Dev,Prod)/(Files/(env,settings)[a..z][0..10].json))";
     //let pattern = "[a..z][A..Z][a..z,a..z].cs";
-    let pattern = "chinese_studies/chars/[0{a..b}..10]_(我,吃,了,一,个,苹,果).char";
-    //let pattern = args.pattern;
 
     let cli = PowerFileCli::parse();
-    let kak = match &cli.command {
-        Commands::Create(args) => 1,
-        Commands::Preview(args) => 1,
-        Commands::Index(args) => 1
-    };
+    match cli.command {
+        Commands::Create(args) => with_config(|config| CreateHandler::new(args, config).handle()),
+        Commands::Undo(args) => UndoHandler::new(args).handle(),
+        Commands::Redo(args) => RedoHandler::new(args).handle(),
+        Commands::History(args) => HistoryHandler::new(args).handle(),
+        Commands::Preview(args) => PreviewHandler::new(args).handle(),
+        Commands::Index(args) => with_config(|config| IndexHandler::new(args, config).handle()),
+        Commands::Match(args) => MatchHandler::new(args).handle(),
+        Commands::Rename(args) => RenameHandler::new(args).handle(),
+        Commands::Infer(args) => InferHandler::new(args).handle(),
+        Commands::Convert(args) => ConvertHandler::new(args).handle(),
+        Commands::Parse(args) => ParseHandler::new(args).handle(),
+        Commands::Config(args) => with_config(|config| ConfigHandler::new(args, config).handle()),
+    }
+}
+
+// Only the commands with configurable settings read the configuration
+fn with_config(run: impl FnOnce(Config) -> ExitCode) -> ExitCode {
+    match Config::load() {
+        Ok(config) => run(config),
+        Err(err) => {
+            eprintln!("Invalid configuration: {}", err);
+            ExitCode::FAILURE
+        }
+    }
 }
 
 #[cfg(test)]
@@ -69,4 +307,4 @@ mod tests {
         use clap::CommandFactory;
         PowerFileCli::command().debug_assert();
     }
-}
\ No newline at end of file
+}
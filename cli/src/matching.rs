@@ -0,0 +1,54 @@
+use crate::diagnostics;
+use crate::MatchArgs;
+use powerfile_core::macros::Definitions;
+use powerfile_core::matcher::{self, Choice};
+use powerfile_core::parser;
+use std::process::ExitCode;
+
+pub struct MatchHandler {
+    args: MatchArgs,
+}
+
+impl MatchHandler {
+    pub fn new(args: MatchArgs) -> Self {
+        MatchHandler { args }
+    }
+
+    /// Succeeds when every path can be produced by the pattern
+    pub fn handle(&self) -> ExitCode {
+        let pattern = &self.args.pattern;
+        let value = match parser::parse(pattern).and_then(|value| Definitions::new().expand(&value))
+        {
+            Ok(value) => value,
+            Err(err) => {
+                diagnostics::report(pattern, pattern, &[err]);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let mut all_matched = true;
+        for path in &self.args.paths {
+            let Some(found) = matcher::matches(&value, path) else {
+                println!("no match  {}", path);
+                all_matched = false;
+                continue;
+            };
+
+            println!("match     {}", path);
+            for (index, capture) in found.captures.iter().enumerate() {
+                let Some(capture) = capture else { continue };
+                let choice = match capture.choice {
+                    Choice::Alternative(alternative) => format!("alternative {}", alternative + 1),
+                    Choice::Char(_) | Choice::Number(_) => "range".to_string(),
+                };
+
+                println!("  {{{}}} {} ({})", index + 1, capture.text, choice);
+            }
+        }
+
+        match all_matched {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::FAILURE,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::diagnostics;
+use crate::ParseArgs;
+use powerfile_core::include::{IncludeOptions, Sources};
+use powerfile_core::parser::{self, ParseOptions, Value};
+use powerfile_core::pattern_file;
+use powerfile_core::resolver::FileListResolver;
+use serde::Serialize;
+use std::ops::Range;
+use std::path::Path;
+use std::process::ExitCode;
+
+pub struct ParseHandler {
+    args: ParseArgs,
+}
+
+/// The machine readable output of `parse --json`
+#[derive(Serialize, Default)]
+struct Output<'source> {
+    entries: Vec<JsonEntry<'source>>,
+    diagnostics: Vec<JsonDiagnostic>,
+}
+
+#[derive(Serialize)]
+struct JsonEntry<'source> {
+    /// The pattern file the entry was read from, `None` for a pattern given on the command line
+    file: Option<String>,
+    span: Range<usize>,
+    ast: Value<'source>,
+}
+
+#[derive(Serialize)]
+struct JsonDiagnostic {
+    file: Option<String>,
+    message: String,
+    span: Range<usize>,
+}
+
+impl ParseHandler {
+    pub fn new(args: ParseArgs) -> Self {
+        ParseHandler { args }
+    }
+
+    /// Prints the AST, failing when the pattern has errors
+    pub fn handle(&self) -> ExitCode {
+        if let Some(path) = &self.args.file {
+            return self.handle_file(path);
+        }
+
+        let pattern = self.args.pattern.as_deref().unwrap_or_default();
+        let mut output = Output::default();
+        match parser::parse(pattern) {
+            Ok(ast) => output.entries.push(JsonEntry {
+                file: None,
+                span: 0..pattern.len(),
+                ast,
+            }),
+            Err((message, span)) => output.diagnostics.push(JsonDiagnostic {
+                file: None,
+                message,
+                span,
+            }),
+        }
+
+        if !self.args.json && !output.diagnostics.is_empty() {
+            let errors = output
+                .diagnostics
+                .into_iter()
+                .map(|d| (d.message, d.span))
+                .collect::<Vec<_>>();
+            diagnostics::report(pattern, pattern, &errors);
+            return ExitCode::FAILURE;
+        }
+
+        self.print(output)
+    }
+
+    fn handle_file(&self, path: &Path) -> ExitCode {
+        let options = IncludeOptions {
+            search_path: self.args.include_dir.clone(),
+        };
+        let sources = match Sources::load(path, &options) {
+            Ok(sources) => sources,
+            Err(err) => {
+                eprintln!("Failed to read pattern file '{}': {}", path.display(), err);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let mut parse_options = ParseOptions::default();
+        if let Some(base_dir) = path.parent() {
+            parse_options.resolver = Box::new(FileListResolver::new(base_dir.to_path_buf()));
+        }
+
+        let name = |file: usize| Some(sources.files()[file].path.to_string_lossy().to_string());
+        let mut output = Output::default();
+        match pattern_file::parse_sources(&sources, &parse_options) {
+            Ok(file) => {
+                diagnostics::warn_sources(&sources, &file.warnings);
+                output.entries = file
+                    .entries
+                    .into_iter()
+                    .map(|entry| JsonEntry {
+                        file: name(entry.file),
+                        span: entry.span,
+                        ast: entry.value,
+                    })
+                    .collect()
+            }
+            Err(errors) if self.args.json => {
+                output.diagnostics = errors
+                    .into_iter()
+                    .map(|d| JsonDiagnostic {
+                        file: name(d.file),
+                        message: d.message,
+                        span: d.span,
+                    })
+                    .collect()
+            }
+            Err(errors) => {
+                diagnostics::report_sources(&sources, &errors);
+                return ExitCode::FAILURE;
+            }
+        }
+
+        self.print(output)
+    }
+
+    fn print(&self, output: Output) -> ExitCode {
+        let failed = !output.diagnostics.is_empty();
+
+        if self.args.json {
+            match serde_json::to_string_pretty(&output) {
+                Ok(json) => println!("{}", json),
+                Err(err) => {
+                    eprintln!("Failed to serialize the AST: {}", err);
+                    return ExitCode::FAILURE;
+                }
+            }
+        } else {
+            for entry in output.entries {
+                println!("{:#?}", entry.ast);
+            }
+        }
+
+        match failed {
+            true => ExitCode::FAILURE,
+            false => ExitCode::SUCCESS,
+        }
+    }
+}
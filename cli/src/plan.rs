@@ -0,0 +1,376 @@
+use crate::ConflictPolicy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    File,
+    Directory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Create,
+    /// An existing path of the same kind that is left as it is
+    Exists,
+    /// Left out following the conflict policy because something is in the way, with the reason why
+    Skip(&'static str),
+    /// An existing file that is replaced
+    Overwrite,
+    /// Created at a free name with a numeric suffix instead
+    Rename,
+    /// Can not be created, with the reason why
+    Conflict(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// The expanded path relative to the root, directories end with '/'
+    pub path: String,
+    /// Where the step ends up, only differs from `path` when it or a parent is renamed
+    pub target: String,
+    pub kind: Kind,
+    pub action: Action,
+    /// The index of the path the step was planned for, none for the parents added before it
+    pub source: Option<usize>,
+}
+
+/// Everything `create` would do, compared against what is on disk.
+///
+/// Parent directories get a step of their own before their first entry, and every path is
+/// planned once. Paths in the way are resolved with the conflict policy while planning, so
+/// nothing is touched before the whole plan is known.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    /// Plans the expanded `paths` below `root`, a path ending in '/' is a directory
+    pub fn new<S: AsRef<str>>(root: &Path, paths: &[S], policy: ConflictPolicy) -> Plan {
+        let mut planner = Planner {
+            root,
+            policy,
+            plan: Plan::default(),
+            planned: HashMap::new(),
+            targets: HashSet::new(),
+        };
+
+        for (source, path) in paths.iter().enumerate() {
+            let path = path.as_ref();
+            let name = path.trim_end_matches('/');
+            if name.is_empty() {
+                continue;
+            }
+
+            for (index, _) in name.match_indices('/').filter(|(index, _)| *index > 0) {
+                planner.add(&name[..index], Kind::Directory, None);
+            }
+            match path.ends_with('/') {
+                true => planner.add(name, Kind::Directory, Some(source)),
+                false => planner.add(name, Kind::File, Some(source)),
+            }
+        }
+
+        planner.plan
+    }
+
+    pub fn count(&self, kind: Kind, action: Action) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| step.kind == kind && step.action == action)
+            .count()
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| matches!(step.action, Action::Skip(_)))
+            .count()
+    }
+
+    pub fn conflicts(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| matches!(step.action, Action::Conflict(_)))
+            .count()
+    }
+}
+
+struct Planner<'root> {
+    root: &'root Path,
+    policy: ConflictPolicy,
+    plan: Plan,
+    // Paths without their trailing '/', to the index of their step
+    planned: HashMap<String, usize>,
+    // Targets without their trailing '/', so renamed paths do not collide
+    targets: HashSet<String>,
+}
+
+impl Planner<'_> {
+    fn add(&mut self, name: &str, kind: Kind, source: Option<usize>) {
+        if let Some(&index) = self.planned.get(name) {
+            let step = &mut self.plan.steps[index];
+            step.source = step.source.or(source);
+            // A path already on disk stays what it is, its entries conflict instead
+            let existing = matches!(
+                step.action,
+                Action::Exists | Action::Skip(_) | Action::Overwrite
+            );
+            if step.kind != kind && !existing {
+                step.action = Action::Conflict("planned as both a file and a directory");
+            }
+            return;
+        }
+
+        // Entries follow their parent into a renamed directory, or are left out with it
+        let (parent, file_name) = match name.rfind('/') {
+            Some(index) => (&name[..index], &name[index..]),
+            None => ("", name),
+        };
+        let parent = self
+            .planned
+            .get(parent)
+            .map(|&index| &self.plan.steps[index]);
+        let target = match parent {
+            Some(parent) => format!("{}{}", parent.target.trim_end_matches('/'), file_name),
+            None => name.to_string(),
+        };
+
+        let action = match parent.map(|parent| (parent.kind, parent.action)) {
+            Some((Kind::File, _)) | Some((_, Action::Conflict(_))) => {
+                Action::Conflict("its parent can not be created")
+            }
+            Some((_, Action::Skip(_))) => Action::Skip("its parent is skipped"),
+            // Nothing can be in a directory that is about to be created
+            Some((_, Action::Create | Action::Rename)) => Action::Create,
+            _ => match fs::metadata(self.root.join(&target)) {
+                Ok(metadata) => match (kind, metadata.is_dir()) {
+                    (Kind::Directory, true) => Action::Exists,
+                    (Kind::File, false) => self.resolve(&target, "already exists", true),
+                    (Kind::Directory, false) => {
+                        self.resolve(&target, "a file is in the way", false)
+                    }
+                    (Kind::File, true) => self.resolve(&target, "a directory is in the way", false),
+                },
+                Err(_) => Action::Create,
+            },
+        };
+
+        let target = match action {
+            Action::Rename => self.free_name(&target),
+            _ => target,
+        };
+        self.targets.insert(target.clone());
+
+        let (path, target) = match kind {
+            Kind::Directory => (format!("{}/", name), format!("{}/", target)),
+            Kind::File => (name.to_string(), target),
+        };
+
+        self.planned.insert(name.to_string(), self.plan.steps.len());
+        self.plan.steps.push(Step {
+            path,
+            target,
+            kind,
+            action,
+            source,
+        });
+    }
+
+    // Applies the conflict policy to an existing `target`, only a file in place of a file can
+    // be overwritten
+    fn resolve(&mut self, target: &str, reason: &'static str, overwritable: bool) -> Action {
+        if self.policy == ConflictPolicy::Prompt {
+            self.policy = prompt(target, reason);
+            let action = self.resolve(target, reason, overwritable);
+            // Only an abort carries over to the next conflict
+            if self.policy != ConflictPolicy::Error {
+                self.policy = ConflictPolicy::Prompt;
+            }
+            return action;
+        }
+
+        match self.policy {
+            // Skipping a file that is already there leaves it as if it had been created
+            ConflictPolicy::Skip if overwritable => Action::Exists,
+            ConflictPolicy::Skip => Action::Skip(reason),
+            ConflictPolicy::Overwrite if overwritable => Action::Overwrite,
+            ConflictPolicy::Rename => Action::Rename,
+            _ => Action::Conflict(reason),
+        }
+    }
+
+    // The first of `name_1`, `name_2`, ... that is neither on disk nor planned, keeping the
+    // extension of files last
+    fn free_name(&self, target: &str) -> String {
+        let path = Path::new(target);
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| format!(".{}", extension))
+            .unwrap_or_default();
+        let parent = &target[..target.len() - stem.len() - extension.len()];
+
+        (1..)
+            .map(|n| format!("{}{}_{}{}", parent, stem, n, extension))
+            .find(|candidate| {
+                !self.targets.contains(candidate)
+                    && fs::symlink_metadata(self.root.join(candidate)).is_err()
+            })
+            .unwrap()
+    }
+}
+
+// Asks on the terminal what to do with a single conflict, anything unexpected aborts
+fn prompt(target: &str, reason: &str) -> ConflictPolicy {
+    eprint!(
+        "'{}': {}. [s]kip, [o]verwrite, [r]ename or [a]bort? ",
+        target, reason
+    );
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return ConflictPolicy::Error;
+    }
+    match answer.trim() {
+        "s" | "skip" => ConflictPolicy::Skip,
+        "o" | "overwrite" => ConflictPolicy::Overwrite,
+        "r" | "rename" => ConflictPolicy::Rename,
+        _ => ConflictPolicy::Error,
+    }
+}
+
+/// Carries out a single step below `root`, files are written with `content`. Its parents must
+/// have been created before.
+pub fn apply(root: &Path, step: &Step, content: &[u8]) -> io::Result<()> {
+    let target = root.join(&step.target);
+
+    match (step.action, step.kind) {
+        (Action::Exists | Action::Skip(_), _) => Ok(()),
+        (Action::Conflict(reason), _) => Err(io::Error::new(io::ErrorKind::AlreadyExists, reason)),
+        (Action::Create | Action::Rename, Kind::Directory) => fs::create_dir(target),
+        (Action::Overwrite, Kind::Directory) => unreachable!("only files are overwritten"),
+        (Action::Create | Action::Rename, Kind::File) => OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(target)?
+            .write_all(content),
+        (Action::Overwrite, Kind::File) => fs::write(target, content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions(plan: &Plan) -> Vec<(&str, Action)> {
+        plan.steps
+            .iter()
+            .map(|step| (step.path.as_str(), step.action))
+            .collect()
+    }
+
+    #[test]
+    fn plans_parents_existing_paths_and_conflicts() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+
+        let plan = Plan::new(
+            root,
+            &[
+                "src/lib.rs",
+                "src/main.rs",
+                "docs/",
+                "src/lib.rs/x",
+                "docs",
+                "a/b/",
+            ],
+            ConflictPolicy::Skip,
+        );
+        assert_eq!(
+            actions(&plan),
+            vec![
+                ("src/", Action::Exists),
+                ("src/lib.rs", Action::Exists),
+                ("src/main.rs", Action::Create),
+                (
+                    "docs/",
+                    Action::Conflict("planned as both a file and a directory")
+                ),
+                (
+                    "src/lib.rs/x",
+                    Action::Conflict("its parent can not be created")
+                ),
+                ("a/", Action::Create),
+                ("a/b/", Action::Create),
+            ]
+        );
+        assert_eq!(plan.count(Kind::Directory, Action::Create), 2);
+        assert_eq!(plan.conflicts(), 2);
+
+        for step in &plan.steps {
+            let _ = apply(root, step, b"");
+        }
+        assert!(root.join("src/main.rs").is_file());
+        assert!(root.join("a/b").is_dir());
+        assert!(!root.join("docs").exists());
+    }
+
+    #[test]
+    fn conflict_policies_resolve_existing_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(root.join("lib/a.rs"), "old").unwrap();
+        fs::write(root.join("lib/a_1.rs"), "").unwrap();
+        fs::write(root.join("out"), "").unwrap();
+
+        let paths = ["lib/a.rs", "out/log.txt"];
+        let targets = |plan: &Plan| -> Vec<(String, Action)> {
+            plan.steps
+                .iter()
+                .map(|step| (step.target.clone(), step.action))
+                .collect()
+        };
+
+        let plan = Plan::new(root, &paths, ConflictPolicy::Rename);
+        assert_eq!(
+            targets(&plan),
+            vec![
+                ("lib/".to_string(), Action::Exists),
+                ("lib/a_2.rs".to_string(), Action::Rename),
+                ("out_1/".to_string(), Action::Rename),
+                ("out_1/log.txt".to_string(), Action::Create),
+            ]
+        );
+
+        let plan = Plan::new(root, &paths, ConflictPolicy::Overwrite);
+        assert_eq!(plan.steps[1].action, Action::Overwrite);
+        assert_eq!(
+            plan.steps[2].action,
+            Action::Conflict("a file is in the way")
+        );
+        assert_eq!(
+            plan.steps[3].action,
+            Action::Conflict("its parent can not be created")
+        );
+
+        apply(root, &plan.steps[1], b"new").unwrap();
+        assert_eq!(fs::read_to_string(root.join("lib/a.rs")).unwrap(), "new");
+
+        let plan = Plan::new(root, &paths, ConflictPolicy::Error);
+        assert_eq!(plan.conflicts(), 3);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::diagnostics;
+use crate::plan::{Action, Kind, Plan};
+use crate::{ConflictPolicy, PreviewArgs};
+use powerfile_core::infer;
+use powerfile_core::interpreter::{Interpreter, TextInterpreter};
+use powerfile_core::limits::{Budget, Limits};
+use powerfile_core::macros::Definitions;
+use powerfile_core::parser;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::process::ExitCode;
+
+pub struct PreviewHandler {
+    args: PreviewArgs,
+}
+
+/// An entry of the tree, named by the last component of its path
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    kind: Option<Kind>,
+    action: Option<Action>,
+    children: Vec<Node>,
+    // Names of the children to their index
+    names: HashMap<String, usize>,
+}
+
+impl Node {
+    fn entries(&self) -> usize {
+        self.children.iter().map(|child| 1 + child.entries()).sum()
+    }
+
+    fn exists(&self) -> bool {
+        self.action == Some(Action::Exists)
+    }
+
+    // Skipped paths are the ones something else is in the way of, `create` leaves them out
+    fn skipped(&self) -> bool {
+        matches!(self.action, Some(Action::Skip(_)))
+    }
+
+    fn conflicts(&self) -> bool {
+        matches!(self.action, Some(Action::Conflict(_)))
+    }
+}
+
+// A line of the tree, either one entry or siblings collapsed into a pattern
+enum Item<'node> {
+    Entry(&'node Node),
+    Collapsed(Kind, Vec<&'node Node>),
+}
+
+impl PreviewHandler {
+    pub fn new(args: PreviewArgs) -> Self {
+        PreviewHandler { args }
+    }
+
+    /// Prints the paths of the pattern as a tree, marking the ones already on disk
+    pub fn handle(&self) -> ExitCode {
+        let color = io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        match self.preview(color) {
+            Some(lines) => {
+                for line in lines {
+                    println!("{}", line);
+                }
+                ExitCode::SUCCESS
+            }
+            None => ExitCode::FAILURE,
+        }
+    }
+
+    // The lines of the tree followed by a summary, none when the pattern can not be expanded
+    fn preview(&self, color: bool) -> Option<Vec<String>> {
+        let pattern = &self.args.pattern;
+        let mut budget = Budget::new(Limits {
+            max_outputs: self.args.limit,
+            ..Limits::default()
+        });
+        let paths = parser::parse(pattern)
+            .and_then(|value| Definitions::new().expand(&value))
+            .map(|value| TextInterpreter.interpret_with(&value, &mut budget));
+
+        let paths = match paths {
+            Ok(Ok(paths)) => paths,
+            Ok(Err(err)) => {
+                eprintln!("{}", err);
+                return None;
+            }
+            Err(err) => {
+                diagnostics::report(pattern, pattern, &[err]);
+                return None;
+            }
+        };
+
+        // The plan knows which paths exist, and adds the parent directories. Skipping leaves
+        // existing files as they are, and what is in the way of a path as skipped.
+        let plan = Plan::new(&self.args.root, &paths, ConflictPolicy::Skip);
+        let tree = build(&plan);
+        let existing = count(&tree, Node::exists);
+        let (skipped, conflicts) = (count(&tree, Node::skipped), count(&tree, Node::conflicts));
+
+        let mut lines = vec![paint(&self.args.root.display().to_string(), "1;34", color)];
+        self.render(&tree, "", 1, color, &mut lines);
+        lines.push(format!(
+            "\n{} directories, {} files, {} skipped, {} already exist, {} conflict",
+            plan.steps
+                .iter()
+                .filter(|step| step.kind == Kind::Directory)
+                .count(),
+            plan.steps
+                .iter()
+                .filter(|step| step.kind == Kind::File)
+                .count(),
+            skipped,
+            existing,
+            conflicts
+        ));
+
+        Some(lines)
+    }
+
+    fn render(
+        &self,
+        node: &Node,
+        prefix: &str,
+        depth: usize,
+        color: bool,
+        lines: &mut Vec<String>,
+    ) {
+        let items = self.collapse(node);
+
+        for (index, item) in items.iter().enumerate() {
+            let last = index + 1 == items.len();
+            let connector = if last { "└── " } else { "├── " };
+
+            match item {
+                Item::Entry(child) => {
+                    let deeper = self.args.depth.is_none_or(|max| depth < max);
+                    let mut line = label(child, color);
+                    if !deeper && !child.children.is_empty() {
+                        line.push_str(&format!(" ({} entries)", child.entries()));
+                    }
+                    lines.push(format!("{}{}{}", prefix, connector, line));
+
+                    if deeper {
+                        let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                        self.render(child, &prefix, depth + 1, color, lines);
+                    }
+                }
+                Item::Collapsed(kind, siblings) => lines.push(format!(
+                    "{}{}{}",
+                    prefix,
+                    connector,
+                    collapsed(*kind, siblings, color)
+                )),
+            }
+        }
+    }
+
+    // When there are more entries than `--max-entries`, siblings only differing in their numbers
+    // are collapsed into a single line, and if that is not enough all files and all directories
+    fn collapse<'node>(&self, node: &'node Node) -> Vec<Item<'node>> {
+        let max = self.args.max_entries;
+        if node.children.len() <= max {
+            return node.children.iter().map(Item::Entry).collect();
+        }
+
+        let items = group(&node.children, |child| shape(&child.name));
+        if items.len() <= max {
+            return items;
+        }
+
+        group(&node.children, |_| String::new())
+    }
+}
+
+// Groups siblings of the same kind with the same key in place of the first of them, keeping
+// the ones alone in their group as entries
+fn group<'node>(children: &'node [Node], key: impl Fn(&Node) -> String) -> Vec<Item<'node>> {
+    let mut groups: Vec<(Kind, String, Vec<&Node>)> = Vec::new();
+    let mut indices: HashMap<(Kind, String), usize> = HashMap::new();
+
+    for child in children {
+        let kind = child.kind.unwrap_or(Kind::File);
+        let key = key(child);
+        match indices.get(&(kind, key.clone())) {
+            Some(&index) => groups[index].2.push(child),
+            None => {
+                indices.insert((kind, key.clone()), groups.len());
+                groups.push((kind, key, vec![child]));
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(kind, _, siblings)| match siblings.len() {
+            1 => Item::Entry(siblings[0]),
+            _ => Item::Collapsed(kind, siblings),
+        })
+        .collect()
+}
+
+// Replaces every run of digits by a single '\0', so `file_1.txt` and `file_20.txt` have the same
+// shape
+fn shape(name: &str) -> String {
+    let mut shape = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c.is_ascii_digit() {
+            true if shape.ends_with('\0') => {}
+            true => shape.push('\0'),
+            false => shape.push(c),
+        }
+    }
+
+    shape
+}
+
+// Puts the steps of the plan into a tree, parents always come before their entries
+fn build(plan: &Plan) -> Node {
+    let mut root = Node::default();
+
+    for step in &plan.steps {
+        let mut node = &mut root;
+        let mut components = step
+            .path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .peekable();
+
+        while let Some(component) = components.next() {
+            let index = match node.names.get(component) {
+                Some(&index) => index,
+                None => {
+                    node.names
+                        .insert(component.to_string(), node.children.len());
+                    node.children.push(Node {
+                        name: component.to_string(),
+                        ..Node::default()
+                    });
+                    node.children.len() - 1
+                }
+            };
+
+            node = &mut node.children[index];
+            if components.peek().is_none() {
+                node.kind = Some(step.kind);
+                node.action = Some(step.action);
+            }
+        }
+    }
+
+    root
+}
+
+// The entries below `node` for which `test` holds
+fn count(node: &Node, test: fn(&Node) -> bool) -> usize {
+    node.children
+        .iter()
+        .map(|child| usize::from(test(child)) + count(child, test))
+        .sum()
+}
+
+fn label(node: &Node, color: bool) -> String {
+    let name = match node.kind {
+        Some(Kind::Directory) => paint(&format!("{}/", node.name), "1;34", color),
+        _ => node.name.clone(),
+    };
+
+    match node.action {
+        Some(Action::Exists) => format!("{} {}", name, paint("(exists)", "2", color)),
+        Some(Action::Skip(reason)) => format!(
+            "{} {}",
+            name,
+            paint(&format!("(skipped: {})", reason), "33", color)
+        ),
+        Some(Action::Conflict(reason)) => format!(
+            "{} {}",
+            name,
+            paint(&format!("(conflict: {})", reason), "31", color)
+        ),
+        _ => name,
+    }
+}
+
+// Siblings as the pattern producing their names, e.g. `file_[0..999].txt (1000 files)`
+fn collapsed(kind: Kind, siblings: &[&Node], color: bool) -> String {
+    let names = siblings
+        .iter()
+        .map(|node| node.name.as_str())
+        .collect::<Vec<_>>();
+    let pattern = infer::infer(&names).unwrap_or_else(|_| "...".to_string());
+
+    let mut line = match kind {
+        Kind::Directory => format!(
+            "{} ({} directories, {} entries)",
+            paint(&format!("{}/", pattern), "1;34", color),
+            siblings.len(),
+            siblings.iter().map(|node| node.entries()).sum::<usize>()
+        ),
+        Kind::File => format!("{} ({} files)", pattern, siblings.len()),
+    };
+
+    let existing = siblings.iter().filter(|node| node.exists()).count();
+    if existing > 0 {
+        line.push_str(&format!(
+            " {}",
+            paint(&format!("({} exist)", existing), "2", color)
+        ));
+    }
+    let skipped = siblings.iter().filter(|node| node.skipped()).count();
+    if skipped > 0 {
+        line.push_str(&format!(
+            " {}",
+            paint(&format!("({} skipped)", skipped), "33", color)
+        ));
+    }
+    let conflicts = siblings.iter().filter(|node| node.conflicts()).count();
+    if conflicts > 0 {
+        line.push_str(&format!(
+            " {}",
+            paint(&format!("({} conflict)", conflicts), "31", color)
+        ));
+    }
+
+    line
+}
+
+fn paint(text: &str, code: &str, color: bool) -> String {
+    match color {
+        true => format!("\x1b[{}m{}\x1b[0m", code, text),
+        false => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn handler(
+        root: &Path,
+        pattern: &str,
+        depth: Option<usize>,
+        max_entries: usize,
+    ) -> PreviewHandler {
+        PreviewHandler::new(PreviewArgs {
+            pattern: pattern.to_string(),
+            root: root.to_path_buf(),
+            depth,
+            max_entries,
+            limit: 10_000,
+        })
+    }
+
+    // The lines of the tree, without the root and the summary
+    fn tree(pattern: &str, depth: Option<usize>, max_entries: usize) -> Vec<String> {
+        let temp = tempfile::tempdir().unwrap();
+        let lines = handler(temp.path(), pattern, depth, max_entries)
+            .preview(false)
+            .unwrap();
+        lines[1..lines.len() - 1].to_vec()
+    }
+
+    #[test]
+    fn renders_a_tree_collapsing_large_sibling_sets() {
+        assert_eq!(
+            tree("src/(main.rs,lib/(a,b).rs,file_[0..999].txt)", None, 20),
+            [
+                "└── src/",
+                "    ├── main.rs",
+                "    ├── lib/",
+                "    │   ├── a.rs",
+                "    │   └── b.rs",
+                "    └── file_[0..999].txt (1000 files)",
+            ]
+        );
+        assert_eq!(
+            tree("(a,b)/(x,y)/z", Some(1), 20),
+            ["├── a/ (4 entries)", "└── b/ (4 entries)"]
+        );
+        assert_eq!(
+            tree("(a,b,c)/z", None, 2),
+            ["└── [a..c]/ (3 directories, 3 entries)"]
+        );
+    }
+
+    #[test]
+    fn marks_existing_paths_and_paths_in_the_way() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join("docs"), "").unwrap();
+
+        let lines = handler(root, "(src/(main,lib).rs,docs/a.md)", None, 20)
+            .preview(false)
+            .unwrap();
+        assert_eq!(
+            lines,
+            [
+                root.display().to_string(),
+                "├── src/ (exists)".to_string(),
+                "│   ├── main.rs (exists)".to_string(),
+                "│   └── lib.rs".to_string(),
+                "└── docs/ (skipped: a file is in the way)".to_string(),
+                "    └── a.md (skipped: its parent is skipped)".to_string(),
+                "\n2 directories, 3 files, 2 skipped, 2 already exist, 0 conflict".to_string(),
+            ]
+        );
+
+        assert!(handler(root, "[1..10001]", None, 20)
+            .preview(false)
+            .is_none());
+    }
+}
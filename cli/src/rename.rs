@@ -0,0 +1,372 @@
+use crate::diagnostics;
+use crate::RenameArgs;
+use powerfile_core::macros::Definitions;
+use powerfile_core::matcher;
+use powerfile_core::parser::{self, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+pub struct RenameHandler {
+    args: RenameArgs,
+}
+
+/// A single rename, paths are relative to the root
+#[derive(Debug, Clone, PartialEq)]
+pub struct Move {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Conflict {
+    /// Several paths would be moved to the same destination
+    SameDestination(String, Vec<String>),
+    /// The destination exists and is not moved out of the way
+    Exists(String),
+}
+
+impl RenameHandler {
+    pub fn new(args: RenameArgs) -> Self {
+        RenameHandler { args }
+    }
+
+    pub fn handle(&self) -> ExitCode {
+        let pattern = &self.args.source;
+        let value = match parser::parse(pattern).and_then(|value| Definitions::new().expand(&value))
+        {
+            Ok(value) => value,
+            Err(err) => {
+                diagnostics::report(pattern, pattern, &[err]);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let root = &self.args.root;
+        let candidates = match find_matching(root, literal_dir(pattern), &value) {
+            Ok(candidates) => candidates,
+            Err(err) => {
+                eprintln!("Failed to scan '{}': {}", root.display(), err);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let mut moves = Vec::new();
+        for (path, found) in candidates {
+            match found.substitute(&self.args.destination) {
+                Ok(to) if to != path => moves.push(Move { from: path, to }),
+                Ok(_) => {}
+                Err(err) => {
+                    eprintln!("Unable to rename '{}': {}", path, err);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+
+        if moves.is_empty() {
+            println!("Nothing to rename");
+            return ExitCode::SUCCESS;
+        }
+
+        let exists = |path: &str| root.join(path).exists();
+        let conflicts = find_conflicts(&moves, exists);
+        print_table(&moves, &conflicts);
+
+        if !conflicts.is_empty() {
+            eprintln!("Aborting, {} conflict(s) found", conflicts.len());
+            return ExitCode::FAILURE;
+        }
+        if self.args.dry_run {
+            return ExitCode::SUCCESS;
+        }
+
+        if let Err((step, err, failures)) = apply_all(root, &order_moves(&moves, exists)) {
+            eprintln!("Failed to move '{}' to '{}': {}", step.from, step.to, err);
+            for failure in &failures {
+                eprintln!("{}", failure);
+            }
+            match failures.len() {
+                0 => eprintln!("Rolled back, nothing was renamed"),
+                n => eprintln!(
+                    "Rolled back partially, {} path(s) could not be moved back",
+                    n
+                ),
+            }
+            return ExitCode::FAILURE;
+        }
+
+        println!("Renamed {} path(s)", moves.len());
+        ExitCode::SUCCESS
+    }
+}
+
+// The directory before the first group, range or directive, nothing outside it can match
+fn literal_dir(pattern: &str) -> &str {
+    let literal = pattern
+        .find(['(', '[', '@', '$', '~', '\\', ','])
+        .map_or(pattern, |index| &pattern[..index]);
+
+    literal.rfind('/').map_or("", |index| &literal[..index])
+}
+
+// Walks `dir` below `root`, a matching directory is not descended into
+fn find_matching(
+    root: &Path,
+    dir: &str,
+    value: &Value,
+) -> io::Result<Vec<(String, matcher::Match)>> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_string()];
+
+    while let Some(dir) = pending.pop() {
+        let path = root.join(&dir);
+        if !path.is_dir() {
+            continue;
+        }
+
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let relative = match dir.is_empty() {
+                true => name,
+                false => format!("{}/{}", dir, name),
+            };
+
+            if let Some(m) = matcher::matches(value, &relative) {
+                found.push((relative, m));
+            } else if entry.file_type()?.is_dir() {
+                pending.push(relative);
+            }
+        }
+    }
+
+    found.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(found)
+}
+
+pub fn find_conflicts(moves: &[Move], exists: impl Fn(&str) -> bool) -> Vec<Conflict> {
+    let sources = moves
+        .iter()
+        .map(|m| m.from.as_str())
+        .collect::<HashSet<_>>();
+    let mut destinations: HashMap<&str, Vec<String>> = HashMap::new();
+    for m in moves {
+        destinations.entry(&m.to).or_default().push(m.from.clone());
+    }
+
+    let mut conflicts = Vec::new();
+    for m in moves {
+        let from = &destinations[m.to.as_str()];
+        if from.len() > 1 {
+            if from[0] == m.from {
+                conflicts.push(Conflict::SameDestination(m.to.clone(), from.clone()));
+            }
+        } else if !sources.contains(m.to.as_str()) && exists(&m.to) {
+            conflicts.push(Conflict::Exists(m.to.clone()));
+        }
+    }
+
+    conflicts
+}
+
+/// Orders the moves so no destination is written before its current occupant moved away.
+///
+/// Cycles, such as swapping two files, are broken by first moving one of them to a temporary name
+/// that neither the moves nor `exists` use.
+pub fn order_moves(moves: &[Move], exists: impl Fn(&str) -> bool) -> Vec<Move> {
+    let mut pending = moves.to_vec();
+    let mut steps = Vec::with_capacity(moves.len());
+    let mut temporary = 0;
+
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .position(|m| !pending.iter().any(|other| other.from == m.to));
+
+        match ready {
+            Some(index) => steps.push(pending.remove(index)),
+            None => {
+                let temp = loop {
+                    temporary += 1;
+                    let temp = format!("{}.powerfile-{}", pending[0].from, temporary);
+                    if !moves.iter().any(|m| m.from == temp || m.to == temp) && !exists(&temp) {
+                        break temp;
+                    }
+                };
+
+                steps.push(Move {
+                    from: pending[0].from.clone(),
+                    to: temp.clone(),
+                });
+                pending[0].from = temp;
+            }
+        }
+    }
+
+    steps
+}
+
+// Applies the moves in order. When one fails the ones before it are moved back, and the failing
+// move is returned with its error and whatever could not be moved back.
+fn apply_all<'a>(root: &Path, steps: &'a [Move]) -> Result<(), (&'a Move, io::Error, Vec<String>)> {
+    let mut done = Vec::new();
+
+    for step in steps {
+        match apply(root, step) {
+            Ok(created) => done.push((step, created)),
+            Err((err, created)) => {
+                let mut failures = remove_dirs(&created);
+                for (step, created) in done.iter().rev() {
+                    if let Err(err) = fs::rename(root.join(&step.to), root.join(&step.from)) {
+                        failures.push(format!("'{}': {}", step.to, err));
+                    }
+                    failures.extend(remove_dirs(created));
+                }
+
+                return Err((step, err, failures));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Returns the directories created for the destination, deepest first, also when it fails
+fn apply(root: &Path, step: &Move) -> Result<Vec<PathBuf>, (io::Error, Vec<PathBuf>)> {
+    let to: PathBuf = root.join(&step.to);
+
+    let mut created = Vec::new();
+    let missing = to
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| !dir.exists())
+        .collect::<Vec<_>>();
+    for dir in missing.into_iter().rev() {
+        match fs::create_dir(dir) {
+            Ok(()) => created.insert(0, dir.to_path_buf()),
+            Err(err) => return Err((err, created)),
+        }
+    }
+
+    match fs::rename(root.join(&step.from), to) {
+        Ok(()) => Ok(created),
+        Err(err) => Err((err, created)),
+    }
+}
+
+fn remove_dirs(dirs: &[PathBuf]) -> Vec<String> {
+    dirs.iter()
+        .filter_map(|dir| {
+            fs::remove_dir(dir)
+                .err()
+                .map(|err| format!("'{}': {}", dir.display(), err))
+        })
+        .collect()
+}
+
+fn print_table(moves: &[Move], conflicts: &[Conflict]) {
+    let conflicting = |m: &Move| {
+        conflicts.iter().any(|c| match c {
+            Conflict::SameDestination(to, _) | Conflict::Exists(to) => *to == m.to,
+        })
+    };
+
+    let width = moves.iter().map(|m| m.from.len()).max().unwrap_or(0);
+    for m in moves {
+        let status = match conflicting(m) {
+            true => "conflict",
+            false => "ok",
+        };
+        println!(
+            "{:<8}  {:<width$}  ->  {}",
+            status,
+            m.from,
+            m.to,
+            width = width
+        );
+    }
+
+    for conflict in conflicts {
+        match conflict {
+            Conflict::SameDestination(to, from) => {
+                eprintln!("'{}' is the destination of {}", to, from.join(", "))
+            }
+            Conflict::Exists(to) => eprintln!("'{}' already exists", to),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mv(from: &str, to: &str) -> Move {
+        Move {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn conflicts_detect_shared_and_existing_destinations() {
+        let moves = vec![mv("a", "x"), mv("b", "x"), mv("c", "d"), mv("d", "e")];
+        let conflicts = find_conflicts(&moves, |path| path == "d" || path == "e");
+
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict::SameDestination("x".to_string(), vec!["a".to_string(), "b".to_string()]),
+                Conflict::Exists("e".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn order_moves_follows_chains_and_breaks_swaps() {
+        let steps = order_moves(&[mv("a", "b"), mv("b", "c")], |_| false);
+        assert_eq!(steps, vec![mv("b", "c"), mv("a", "b")]);
+
+        let steps = order_moves(&[mv("a", "b"), mv("b", "a")], |_| false);
+        assert_eq!(
+            steps,
+            vec![
+                mv("a", "a.powerfile-1"),
+                mv("b", "a"),
+                mv("a.powerfile-1", "b")
+            ]
+        );
+
+        let steps = order_moves(&[mv("a", "b"), mv("b", "a")], |path| {
+            path == "a.powerfile-1"
+        });
+        assert_eq!(steps[0], mv("a", "a.powerfile-2"));
+    }
+
+    #[test]
+    fn apply_all_moves_everything_back_after_a_failure() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::write(root.join("a"), "a").unwrap();
+        fs::write(root.join("b"), "b").unwrap();
+        fs::write(root.join("file"), "").unwrap();
+
+        // The last move needs a directory where a file is
+        let steps = [mv("a", "new/dir/a"), mv("b", "file/b")];
+        let (step, _, failures) = apply_all(root, &steps).unwrap_err();
+
+        assert_eq!(step, &steps[1]);
+        assert!(failures.is_empty());
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "a");
+        assert!(root.join("b").exists());
+        assert!(!root.join("new").exists());
+    }
+
+    #[test]
+    fn literal_dir_stops_at_first_group() {
+        assert_eq!(literal_dir("src/models/(a,b)_x.rs"), "src/models");
+        assert_eq!(literal_dir("src/a(b)"), "src");
+        assert_eq!(literal_dir("[a..b]/x"), "");
+    }
+}
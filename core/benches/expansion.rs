@@ -0,0 +1,78 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use powerfile_core::expansion::Expansion;
+use powerfile_core::interpreter::{Interpreter, TextInterpreter};
+use powerfile_core::parallel::ParallelTextInterpreter;
+use powerfile_core::parser::{self, Value};
+
+// 4 * 250 * 26 * 10 * 2 = 520,000 paths
+const PATTERN: &str = "fixtures/(dev,test,staging,prod)/[1..250]/file_[a..z][0..9].(json,yaml)";
+
+// The recursive expansion the text interpreter used before, kept as the baseline
+fn baseline(value: &Value) -> Vec<String> {
+    match value {
+        Value::Text(s) => vec![s.to_string()],
+        Value::TextGroup(group) => group.iter().flat_map(baseline).collect(),
+        Value::ExpandableGroup(group) => {
+            let mut current = Vec::new();
+            for expander in group {
+                current = cartesian_product(current, baseline(expander));
+            }
+
+            current
+        }
+        Value::CharRange(start, end) => (*start..=*end).map(|x| x.to_string()).collect(),
+        Value::NumberRange(start, end) => (*start..=*end).map(|x| x.to_string()).collect(),
+        Value::Reference(..) => unreachable!("the pattern has no references"),
+    }
+}
+
+fn cartesian_product(left: Vec<String>, right: Vec<String>) -> Vec<String> {
+    if left.is_empty() {
+        return right;
+    }
+    if right.is_empty() {
+        return left;
+    }
+
+    let mut combined = Vec::new();
+    for l in &left {
+        for r in &right {
+            combined.push(format!("{}{}", l, r));
+        }
+    }
+
+    combined
+}
+
+fn expansion(c: &mut Criterion) {
+    let value = parser::parse(PATTERN).unwrap();
+    let mut group = c.benchmark_group("expansion");
+    group.sample_size(10);
+
+    group.bench_function("baseline", |b| b.iter(|| baseline(black_box(&value))));
+    group.bench_function("sequential", |b| {
+        b.iter(|| TextInterpreter.interpret(black_box(&value)).unwrap())
+    });
+    group.bench_function("buffer", |b| {
+        b.iter(|| {
+            let mut expansion = Expansion::new(black_box(&value)).unwrap();
+            let mut bytes = 0;
+            while let Some(path) = expansion.next() {
+                bytes += path.len();
+            }
+            bytes
+        })
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| {
+            ParallelTextInterpreter
+                .interpret(black_box(&value))
+                .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, expansion);
+criterion_main!(benches);
@@ -0,0 +1,565 @@
+use crate::parser::Value;
+
+/// The most items a sequence without a range equivalent, such as `{1..9..2}`, is written out as
+pub const MAX_SEQUENCE_ITEMS: u64 = 10_000;
+
+/// The result of converting between bash brace expansion and powerfile patterns
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    pub output: String,
+    /// Constructs whose meaning could not be carried over exactly
+    pub warnings: Vec<String>,
+}
+
+/// Converts a bash brace expression, such as `src/{a,b}_{1..3}.rs`, into a pattern.
+///
+/// Every word of the input becomes a top level alternative. Quotes and backslashes are honoured,
+/// a `{` without a matching `}` or without a comma or sequence inside is kept literally.
+pub fn from_brace(input: &str) -> Result<Conversion, String> {
+    let mut importer = Importer::default();
+    let words = split_words(input)?
+        .iter()
+        .map(|word| importer.convert(word, true))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Conversion {
+        output: words.join(","),
+        warnings: importer.warnings,
+    })
+}
+
+/// Converts an expanded pattern into a bash brace expression, top level alternatives become
+/// separate words.
+pub fn to_brace(value: &Value) -> Result<Conversion, String> {
+    let mut exporter = Exporter::default();
+    let output = match value {
+        Value::TextGroup(alternatives) => alternatives
+            .iter()
+            .map(|alternative| exporter.convert(alternative))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(" "),
+        other => exporter.convert(other)?,
+    };
+
+    Ok(Conversion {
+        output,
+        warnings: exporter.warnings,
+    })
+}
+
+// Splits on unquoted whitespace, which patterns can not express anywhere else
+fn split_words(input: &str) -> Result<Vec<&str>, String> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut chars = input.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(start) = start.take() {
+                    words.push(&input[start..index]);
+                }
+                continue;
+            }
+            '\\' => match chars.next() {
+                Some((_, c)) if c.is_whitespace() => {
+                    return Err("Escaped whitespace can not be expressed in a pattern".to_owned())
+                }
+                _ => {}
+            },
+            '\'' | '"' => {
+                let quoted = input[index + 1..]
+                    .find(c)
+                    .ok_or_else(|| format!("Unterminated {} quote", c))?;
+                if input[index + 1..index + 1 + quoted].contains(char::is_whitespace) {
+                    return Err("Quoted whitespace can not be expressed in a pattern".to_owned());
+                }
+                chars.nth(input[index + 1..index + 1 + quoted].chars().count());
+            }
+            _ => {}
+        }
+
+        start.get_or_insert(index);
+    }
+
+    if let Some(start) = start {
+        words.push(&input[start..]);
+    }
+
+    Ok(words)
+}
+
+#[derive(Default)]
+struct Importer {
+    warnings: Vec<String>,
+}
+
+impl Importer {
+    // `leading` is set at the start of a word, where bash expands '~'
+    fn convert(&mut self, word: &str, leading: bool) -> Result<String, String> {
+        let mut result = String::new();
+        let mut rest = word;
+
+        while let Some(c) = rest.chars().next() {
+            let at_start = leading && result.is_empty();
+            rest = &rest[c.len_utf8()..];
+
+            match c {
+                '\\' => match rest.chars().next() {
+                    Some(escaped) => {
+                        result.push_str(&escape(escaped));
+                        rest = &rest[escaped.len_utf8()..];
+                    }
+                    None => result.push_str("\\\\"),
+                },
+                '\'' | '"' => {
+                    let end = rest.find(c).unwrap_or(rest.len());
+                    if c == '"' && rest[..end].contains('$') {
+                        self.warnings
+                            .push(format!("'$' inside \"{}\" is kept literally", &rest[..end]));
+                    }
+                    result.extend(rest[..end].chars().map(escape));
+                    rest = rest.get(end + 1..).unwrap_or_default();
+                }
+                '$' => match variable(rest) {
+                    Some(name) => {
+                        self.warnings
+                            .push(format!("'${}' is only interpolated with --env", name));
+                        result.push('$');
+                        result.push_str(name);
+                        rest = &rest[name.len()..];
+                    }
+                    // Anything else after a '$' is expanded by bash in ways patterns can not
+                    None if rest
+                        .starts_with(|c: char| c.is_alphanumeric() || "{(#?*@!-_".contains(c)) =>
+                    {
+                        return Err(format!("Unsupported parameter expansion '${}'", rest))
+                    }
+                    None => result.push_str("\\$"),
+                },
+                '~' if at_start => {
+                    self.warnings
+                        .push("'~' is only expanded with --env".to_owned());
+                    result.push('~');
+                }
+                '{' => match closing_brace(rest) {
+                    Some(close) => {
+                        result.push_str(&self.brace(&rest[..close], at_start)?);
+                        rest = &rest[close + 1..];
+                    }
+                    None => result.push('{'),
+                },
+                other => result.push_str(&escape(other)),
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Converts the contents of a `{...}`
+    fn brace(&mut self, content: &str, leading: bool) -> Result<String, String> {
+        if let Some(sequence) = Sequence::parse(content) {
+            return self.sequence(content, sequence);
+        }
+
+        let parts = split_alternatives(content);
+        if parts.len() < 2 {
+            // Bash keeps the braces, anything inside may still expand
+            return Ok(format!("{{{}}}", self.convert(content, false)?));
+        }
+
+        if parts.iter().any(|part| part.is_empty()) {
+            return Err(format!(
+                "Empty alternative in '{{{}}}', patterns never produce empty alternatives",
+                content
+            ));
+        }
+
+        let alternatives = parts
+            .iter()
+            .map(|part| self.convert(part, leading))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(format!("({})", alternatives.join(",")))
+    }
+
+    fn sequence(&mut self, content: &str, sequence: Sequence) -> Result<String, String> {
+        let simple =
+            sequence.step.is_none_or(|step| step.unsigned_abs() <= 1) && sequence.width.is_none();
+        let in_range = |n: i64| u32::try_from(n).is_ok();
+
+        match (sequence.start, sequence.end) {
+            (Bound::Number(start), Bound::Number(end))
+                if simple && in_range(start) && in_range(end) =>
+            {
+                self.reversed(content, start > end);
+                Ok(format!("[{}..{}]", start.min(end), start.max(end)))
+            }
+            (Bound::Char(start), Bound::Char(end)) if simple => {
+                self.reversed(content, start > end);
+                Ok(format!("[{}..{}]", start.min(end), start.max(end)))
+            }
+            _ => {
+                let items = sequence.items().ok_or_else(|| {
+                    format!(
+                        "'{{{}}}' has no range equivalent and more than {} items to write out",
+                        content, MAX_SEQUENCE_ITEMS
+                    )
+                })?;
+                self.warnings.push(format!(
+                    "'{{{}}}' has no range equivalent and is written out as {} alternatives",
+                    content,
+                    items.len()
+                ));
+
+                Ok(format!("({})", items.join(",")))
+            }
+        }
+    }
+
+    fn reversed(&mut self, content: &str, reversed: bool) {
+        if reversed {
+            self.warnings.push(format!(
+                "'{{{}}}' counts down, ranges always count up",
+                content
+            ));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Bound {
+    Number(i64),
+    Char(char),
+}
+
+// A bash sequence expression, `{start..end}` or `{start..end..step}`
+struct Sequence {
+    start: Bound,
+    end: Bound,
+    step: Option<i64>,
+    // Width of the widest number when either has a leading zero, bash then pads every number
+    width: Option<usize>,
+}
+
+impl Sequence {
+    fn parse(content: &str) -> Option<Sequence> {
+        let parts = content.split("..").collect::<Vec<_>>();
+        let (start, end, step) = match parts[..] {
+            [start, end] => (start, end, None),
+            [start, end, step] => (start, end, Some(step.parse::<i64>().ok()?)),
+            _ => return None,
+        };
+
+        let bound = |text: &str| match text.parse::<i64>() {
+            Ok(number) => Some(Bound::Number(number)),
+            Err(_) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_alphabetic() => Some(Bound::Char(c)),
+                    _ => None,
+                }
+            }
+        };
+        let padded = [start, end].iter().any(|text| {
+            let digits = text.trim_start_matches('-');
+            digits.len() > 1 && digits.starts_with('0')
+        });
+        let width = padded.then(|| start.len().max(end.len()));
+
+        match (bound(start)?, bound(end)?) {
+            (start @ Bound::Number(_), end @ Bound::Number(_))
+            | (start @ Bound::Char(_), end @ Bound::Char(_)) => Some(Sequence {
+                start,
+                end,
+                step,
+                width: width.filter(|_| matches!(start, Bound::Number(_))),
+            }),
+            _ => None,
+        }
+    }
+
+    // The escaped items in the order bash produces them, `None` when there are more than
+    // `MAX_SEQUENCE_ITEMS`. Counted in i128, which holds the distance between any two i64.
+    fn items(&self) -> Option<Vec<String>> {
+        let width = self.width.unwrap_or(0);
+        let (start, end) = match (self.start, self.end) {
+            (Bound::Number(start), Bound::Number(end)) => (start as i128, end as i128),
+            (Bound::Char(start), Bound::Char(end)) => (start as i128, end as i128),
+            _ => unreachable!("sequences are parsed with matching bounds"),
+        };
+
+        // Bash ignores the sign of the step and counts towards the end
+        let step = self
+            .step
+            .map_or(1, |step| step.unsigned_abs().max(1) as i128);
+        let step = if start > end { -step } else { step };
+        let count = (end - start) / step + 1;
+        if count > MAX_SEQUENCE_ITEMS as i128 {
+            return None;
+        }
+
+        let items = (0..count)
+            .map(|i| start + i * step)
+            .map(|item| match self.start {
+                Bound::Char(_) => escape(char::from_u32(item as u32).unwrap_or_default()),
+                Bound::Number(_) if item < 0 => {
+                    format!("-{:0width$}", -item, width = width.saturating_sub(1))
+                }
+                Bound::Number(_) => format!("{:0width$}", item, width = width),
+            })
+            .collect();
+
+        Some(items)
+    }
+}
+
+// The index of the '}' closing a brace whose '{' was just consumed
+fn closing_brace(rest: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut chars = rest.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\'' | '"' => {
+                let end = rest[index + 1..].find(c)?;
+                chars.nth(rest[index + 1..index + 1 + end].chars().count());
+            }
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(index),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// Splits the contents of a brace at commas outside of nested braces and quotes
+fn split_alternatives(content: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut chars = content.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\'' | '"' => {
+                if let Some(end) = content[index + 1..].find(c) {
+                    chars.nth(content[index + 1..index + 1 + end].chars().count());
+                }
+            }
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&content[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&content[start..]);
+    parts
+}
+
+// The name of a `$NAME` or `{NAME}` variable following a '$', as written
+fn variable(rest: &str) -> Option<&str> {
+    let is_name = |name: &str| {
+        let mut chars = name.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+
+    if rest.starts_with('{') {
+        let end = rest.find('}')?;
+        return is_name(&rest[1..end]).then(|| &rest[..=end]);
+    }
+
+    let end = rest
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(rest.len());
+    is_name(&rest[..end]).then(|| &rest[..end])
+}
+
+fn escape(c: char) -> String {
+    match c {
+        '(' | ')' | '[' | ']' | ',' | '@' | '$' | '~' | '\\' | '#' => format!("\\{}", c),
+        c => c.to_string(),
+    }
+}
+
+#[derive(Default)]
+struct Exporter {
+    warnings: Vec<String>,
+}
+
+impl Exporter {
+    fn convert(&mut self, value: &Value) -> Result<String, String> {
+        match value {
+            Value::Text(text) => Ok(text.chars().map(shell_escape).collect()),
+            Value::ExpandableGroup(members) => members.iter().map(|m| self.convert(m)).collect(),
+            Value::TextGroup(alternatives) if alternatives.len() == 1 => {
+                self.convert(&alternatives[0])
+            }
+            Value::TextGroup(alternatives) => {
+                let alternatives = alternatives
+                    .iter()
+                    .map(|alternative| self.convert(alternative))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(match alternatives.is_empty() {
+                    true => String::new(),
+                    false => format!("{{{}}}", alternatives.join(",")),
+                })
+            }
+            Value::NumberRange(start, end) => Ok(self.range(
+                format!("[{}..{}]", start, end),
+                start > end,
+                match start == end {
+                    true => start.to_string(),
+                    false => format!("{{{}..{}}}", start, end),
+                },
+            )),
+            Value::CharRange(start, end) if start == end => Ok(shell_escape(*start)),
+            Value::CharRange(start, end)
+                if start.is_ascii_alphabetic() && end.is_ascii_alphabetic()
+                    || start.is_ascii_digit() && end.is_ascii_digit() =>
+            {
+                Ok(self.range(
+                    format!("[{}..{}]", start, end),
+                    start > end,
+                    format!("{{{}..{}}}", start, end),
+                ))
+            }
+            Value::CharRange(start, end) => {
+                let items = (*start..=*end).map(shell_escape).collect::<Vec<_>>();
+                if items.len() > 1 {
+                    self.warnings.push(format!(
+                        "Bash only has letter ranges, '[{}..{}]' is written out as {} alternatives",
+                        start,
+                        end,
+                        items.len()
+                    ));
+                }
+
+                Ok(match items.len() {
+                    0 => String::new(),
+                    1 => items[0].clone(),
+                    _ => format!("{{{}}}", items.join(",")),
+                })
+            }
+            Value::Reference(name, _) => Err(format!(
+                "Reference '@{}' must be expanded before converting",
+                name
+            )),
+        }
+    }
+
+    // Bash counts down where an empty range produces nothing
+    fn range(&mut self, range: String, empty: bool, brace: String) -> String {
+        if !empty {
+            return brace;
+        }
+
+        self.warnings.push(format!(
+            "'{}' is empty and is left out, bash would count down",
+            range
+        ));
+        String::new()
+    }
+}
+
+fn shell_escape(c: char) -> String {
+    match c.is_alphanumeric() || "-_./+=:%^@".contains(c) {
+        true => c.to_string(),
+        false => format!("\\{}", c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, TextInterpreter};
+    use crate::parser::parse;
+
+    fn import(input: &str) -> Conversion {
+        from_brace(input).unwrap()
+    }
+
+    fn expand(pattern: &str) -> Vec<String> {
+        TextInterpreter.interpret(&parse(pattern).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn imports_alternatives_and_sequences() {
+        let conversion = import("src/{models,views}/{a..c}_{1..10}.rs README.md");
+
+        assert_eq!(
+            conversion.output,
+            "src/(models,views)/[a..c]_[1..10].rs,README.md"
+        );
+        assert!(conversion.warnings.is_empty());
+
+        // Commas outside braces and lone braces are literal in bash
+        assert_eq!(import("a,b{c}{d").output, "a\\,b{c}{d");
+        assert_eq!(import("x{a,{b,c}}").output, "x(a,(b,c))");
+    }
+
+    #[test]
+    fn imports_differing_semantics_with_warnings() {
+        let conversion = import("f{01..03}");
+        assert_eq!(conversion.output, "f(01,02,03)");
+        assert_eq!(conversion.warnings.len(), 1);
+
+        let conversion = import("{1..7..3}{c..a}");
+        assert_eq!(conversion.output, "(1,4,7)[a..c]");
+        assert_eq!(conversion.warnings.len(), 2);
+
+        let err = from_brace("x{a,}").unwrap_err();
+        assert!(err.contains("Empty alternative in '{a,}'"), "{}", err);
+        assert!(from_brace("{,a}b").is_err());
+
+        assert_eq!(
+            expand(&import("'(x)'\\@{-1..1}").output),
+            ["(x)@-1", "(x)@0", "(x)@1"]
+        );
+        assert!(from_brace("'a b'").is_err());
+    }
+
+    #[test]
+    fn imports_extreme_sequences_without_overflowing() {
+        assert_eq!(import("a{1..5..-9223372036854775808}").output, "a(1)");
+        assert_eq!(
+            import("{9223372036854775806..9223372036854775807}").output,
+            "(9223372036854775806,9223372036854775807)"
+        );
+
+        let err = from_brace("{-9223372036854775808..9223372036854775807..2}").unwrap_err();
+        assert!(err.contains("more than 10000 items"), "{}", err);
+        assert!(from_brace("{1..999999999999}").is_err());
+    }
+
+    #[test]
+    fn exports_expanded_patterns() {
+        let value = parse("src/(models,views)/[a..c]_[1..10].rs,f\\(1\\)").unwrap();
+        let conversion = to_brace(&value).unwrap();
+        assert_eq!(
+            conversion.output,
+            "src/{models,views}/{a..c}_{1..10}.rs f\\(1\\)"
+        );
+        assert!(conversion.warnings.is_empty());
+
+        let conversion = to_brace(&parse("[!..#]").unwrap()).unwrap();
+        assert_eq!(conversion.output, "{\\!,\\\",\\#}");
+        assert_eq!(conversion.warnings.len(), 1);
+
+        assert!(to_brace(&parse("@name").unwrap()).is_err());
+    }
+}
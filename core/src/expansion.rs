@@ -0,0 +1,475 @@
+use crate::interpreter::InterpretError;
+use crate::limits::{Limits, Measure};
+use crate::matcher;
+use crate::parser::Value;
+use std::fmt::Write as _;
+use std::io;
+
+/// Produces the paths of a pattern one at a time in a single reused buffer.
+///
+/// Paths come in the order of the [`crate::interpreter::TextInterpreter`]. Like an odometer,
+/// moving to the next path only advances the last member that can still change, so only the
+/// part of the buffer after the text that stays the same is rewritten.
+pub struct Expansion<'value> {
+    root: Option<Cursor<'value>>,
+    buffer: String,
+    started: bool,
+    // The number of capture slots of the pattern
+    slots: usize,
+}
+
+impl<'value> Expansion<'value> {
+    pub fn new(value: &'value Value<'_>) -> Result<Self, InterpretError> {
+        Self::with_buffer(value, String::new())
+    }
+
+    /// Expands `value` after checking it stays within `limits`
+    pub fn with_limits(value: &'value Value<'_>, limits: &Limits) -> Result<Self, InterpretError> {
+        limits.check(value).map_err(InterpretError::Limit)?;
+        Self::new(value)
+    }
+
+    /// Expands into `buffer`, which is cleared first, so its allocation can be reused
+    pub fn with_buffer(
+        value: &'value Value<'_>,
+        mut buffer: String,
+    ) -> Result<Self, InterpretError> {
+        buffer.clear();
+
+        // The pattern itself has no slot, like when matching
+        let mut slots = 0;
+        Ok(Expansion {
+            root: Cursor::new(value, &mut slots, false)?,
+            buffer,
+            started: false,
+            slots,
+        })
+    }
+
+    /// Moves to the next path and returns it, `None` once every path has been produced
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&str> {
+        let root = self.root.as_mut()?;
+
+        let more = match self.started {
+            true => root.advance(&mut self.buffer),
+            false => {
+                self.started = true;
+                root.first(&mut self.buffer);
+                true
+            }
+        };
+
+        match more {
+            true => Some(&self.buffer),
+            false => {
+                self.root = None;
+                None
+            }
+        }
+    }
+
+    /// The text every group and range produced for the current path, numbered like the
+    /// captures of [`crate::matcher::matches`]. Groups and ranges within alternatives that were
+    /// not chosen are `None`.
+    pub fn captures(&self) -> Vec<Option<&str>> {
+        let mut captures = vec![None; self.slots];
+        if let (Some(root), true) = (&self.root, self.started) {
+            root.capture(&self.buffer, &mut captures);
+        }
+
+        captures
+    }
+
+    /// Gives back the buffer, so its allocation can be reused
+    pub fn into_buffer(self) -> String {
+        self.buffer
+    }
+}
+
+/// Writes every path of `value` to `sink`, each followed by a newline, after checking `limits`.
+///
+/// Returns the number of paths written.
+pub fn write_paths(value: &Value, limits: &Limits, sink: &mut impl io::Write) -> io::Result<u64> {
+    let invalid = |err: InterpretError| io::Error::new(io::ErrorKind::InvalidInput, err);
+
+    let mut expansion = Expansion::with_limits(value, limits).map_err(invalid)?;
+
+    let mut written = 0;
+    while let Some(path) = expansion.next() {
+        sink.write_all(path.as_bytes())?;
+        sink.write_all(b"\n")?;
+        written += 1;
+    }
+
+    Ok(written)
+}
+
+// The state of one value of the pattern, members producing nothing are left out. `start` is
+// where the output of the value begins in the buffer, `slot` is its capture slot.
+enum Cursor<'value> {
+    Text(&'value str),
+    Alternatives {
+        alternatives: Vec<Cursor<'value>>,
+        current: usize,
+        start: usize,
+        slot: Option<usize>,
+    },
+    Sequence {
+        members: Vec<Cursor<'value>>,
+        start: usize,
+    },
+    Chars {
+        first: char,
+        last: char,
+        current: char,
+        start: usize,
+        slot: Option<usize>,
+    },
+    Numbers {
+        first: u32,
+        last: u32,
+        current: u32,
+        start: usize,
+        slot: Option<usize>,
+    },
+}
+
+impl<'value> Cursor<'value> {
+    // `None` for values that produce nothing. Slots are numbered from `next_slot` in the order
+    // the groups and ranges are written, skipped values still use up theirs.
+    fn new(
+        value: &'value Value<'_>,
+        next_slot: &mut usize,
+        captured: bool,
+    ) -> Result<Option<Self>, InterpretError> {
+        let slot = captured.then(|| {
+            *next_slot += 1;
+            *next_slot - 1
+        });
+
+        let cursor = match value {
+            Value::Text(text) => Cursor::Text(text),
+            Value::TextGroup(alternatives) => {
+                let alternatives = Self::all(alternatives, next_slot)?;
+                if alternatives.is_empty() {
+                    return Ok(None);
+                }
+
+                Cursor::Alternatives {
+                    alternatives,
+                    current: 0,
+                    start: 0,
+                    slot,
+                }
+            }
+            Value::ExpandableGroup(members) => {
+                let members = Self::all(members, next_slot)?;
+                if members.is_empty() {
+                    return Ok(None);
+                }
+
+                Cursor::Sequence { members, start: 0 }
+            }
+            _ if Measure::of(value).outputs == 0 => return Ok(None),
+            Value::CharRange(first, last) => Cursor::Chars {
+                first: *first,
+                last: *last,
+                current: *first,
+                start: 0,
+                slot,
+            },
+            Value::NumberRange(first, last) => Cursor::Numbers {
+                first: *first,
+                last: *last,
+                current: *first,
+                start: 0,
+                slot,
+            },
+            Value::Reference(name, span) => {
+                return Err(InterpretError::UnexpandedReference(
+                    name.to_string(),
+                    span.clone(),
+                ))
+            }
+        };
+
+        Ok(Some(cursor))
+    }
+
+    fn all(
+        values: &'value [Value<'_>],
+        next_slot: &mut usize,
+    ) -> Result<Vec<Self>, InterpretError> {
+        let mut cursors = Vec::with_capacity(values.len());
+        for value in values {
+            cursors.extend(Cursor::new(value, next_slot, matcher::is_captured(value))?);
+        }
+
+        Ok(cursors)
+    }
+
+    fn start(&self) -> usize {
+        match self {
+            Cursor::Text(_) => unreachable!("text is never rewritten on its own"),
+            Cursor::Alternatives { start, .. }
+            | Cursor::Sequence { start, .. }
+            | Cursor::Chars { start, .. }
+            | Cursor::Numbers { start, .. } => *start,
+        }
+    }
+
+    // The length of the current output
+    fn len(&self) -> usize {
+        match self {
+            Cursor::Text(text) => text.len(),
+            Cursor::Alternatives {
+                alternatives,
+                current,
+                ..
+            } => alternatives[*current].len(),
+            Cursor::Sequence { members, .. } => members.iter().map(Cursor::len).sum(),
+            Cursor::Chars { current, .. } => current.len_utf8(),
+            Cursor::Numbers { current, .. } => current.checked_ilog10().unwrap_or(0) as usize + 1,
+        }
+    }
+
+    // Fills the slots of this value and the chosen values within it from `buffer`
+    fn capture<'buffer>(&self, buffer: &'buffer str, captures: &mut [Option<&'buffer str>]) {
+        let slot = match self {
+            Cursor::Text(_) => None,
+            Cursor::Alternatives {
+                alternatives,
+                current,
+                slot,
+                ..
+            } => {
+                alternatives[*current].capture(buffer, captures);
+                *slot
+            }
+            Cursor::Sequence { members, .. } => {
+                for member in members {
+                    member.capture(buffer, captures);
+                }
+                None
+            }
+            Cursor::Chars { slot, .. } | Cursor::Numbers { slot, .. } => *slot,
+        };
+
+        if let Some(slot) = slot {
+            let start = self.start();
+            captures[slot] = Some(&buffer[start..start + self.len()]);
+        }
+    }
+
+    // Moves to the first path, appending it to `buffer`
+    fn first(&mut self, buffer: &mut String) {
+        match self {
+            Cursor::Text(text) => buffer.push_str(text),
+            Cursor::Alternatives {
+                alternatives,
+                current,
+                start,
+                ..
+            } => {
+                *current = 0;
+                *start = buffer.len();
+                alternatives[0].first(buffer);
+            }
+            Cursor::Sequence { members, start } => {
+                *start = buffer.len();
+                for member in members {
+                    member.first(buffer);
+                }
+            }
+            Cursor::Chars {
+                first,
+                current,
+                start,
+                ..
+            } => {
+                *current = *first;
+                *start = buffer.len();
+                buffer.push(*current);
+            }
+            Cursor::Numbers {
+                first,
+                current,
+                start,
+                ..
+            } => {
+                *current = *first;
+                *start = buffer.len();
+                let _ = write!(buffer, "{}", current);
+            }
+        }
+    }
+
+    // Moves to the next path, `buffer` must end with the current one. Returns false when there
+    // is no next path, the caller then drops what is left of the current one.
+    fn advance(&mut self, buffer: &mut String) -> bool {
+        match self {
+            Cursor::Text(_) => false,
+            Cursor::Alternatives {
+                alternatives,
+                current,
+                start,
+                ..
+            } => {
+                if alternatives[*current].advance(buffer) {
+                    return true;
+                }
+                if *current + 1 == alternatives.len() {
+                    return false;
+                }
+
+                *current += 1;
+                buffer.truncate(*start);
+                alternatives[*current].first(buffer);
+                true
+            }
+            Cursor::Sequence { members, .. } => {
+                let Some(index) = Self::advance_last(members, buffer) else {
+                    return false;
+                };
+
+                // Every later member starts over after the one that changed
+                for member in &mut members[index + 1..] {
+                    member.first(buffer);
+                }
+                true
+            }
+            Cursor::Chars {
+                last,
+                current,
+                start,
+                ..
+            } => {
+                let Some(next) = (*current..=*last).nth(1) else {
+                    return false;
+                };
+
+                *current = next;
+                buffer.truncate(*start);
+                buffer.push(next);
+                true
+            }
+            Cursor::Numbers {
+                last,
+                current,
+                start,
+                ..
+            } => {
+                if current >= last {
+                    return false;
+                }
+
+                *current += 1;
+                buffer.truncate(*start);
+                let _ = write!(buffer, "{}", current);
+                true
+            }
+        }
+    }
+
+    // Advances the last member that has a next path, dropping the output of the members after
+    // it from the buffer. Returns the index of that member.
+    fn advance_last(members: &mut [Cursor], buffer: &mut String) -> Option<usize> {
+        let mut end = buffer.len();
+
+        for index in (0..members.len()).rev() {
+            if let Cursor::Text(text) = &members[index] {
+                end -= text.len();
+                continue;
+            }
+
+            let start = members[index].start();
+            buffer.truncate(end);
+            if members[index].advance(buffer) {
+                return Some(index);
+            }
+            end = start;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn collect(pattern: &str) -> Vec<String> {
+        let value = parse(pattern).unwrap();
+        let mut expansion = Expansion::new(&value).unwrap();
+
+        let mut paths = Vec::new();
+        while let Some(path) = expansion.next() {
+            paths.push(path.to_owned());
+        }
+
+        paths
+    }
+
+    #[test]
+    fn advances_like_an_odometer() {
+        assert_eq!(
+            collect("src/(a,bcd)_[9..10].rs"),
+            ["src/a_9.rs", "src/a_10.rs", "src/bcd_9.rs", "src/bcd_10.rs"]
+        );
+        assert_eq!(
+            collect("(a(b,c)d,e[1..2](f,g))(h,i)"),
+            [
+                "abdh", "abdi", "acdh", "acdi", "e1fh", "e1fi", "e1gh", "e1gi", "e2fh", "e2fi",
+                "e2gh", "e2gi"
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_members_producing_nothing() {
+        assert_eq!(
+            collect("[x..y]()(,)x/(1,(2))"),
+            ["xx/1", "xx/2", "yx/1", "yx/2"]
+        );
+        assert_eq!(collect("a[3..1]b"), ["ab"]);
+        assert!(collect("()").is_empty());
+    }
+
+    #[test]
+    fn captures_are_numbered_like_matches() {
+        let value = parse("(a,()[1..2],b(c,d))/[x..y]_[9..10]").unwrap();
+        let mut expansion = Expansion::new(&value).unwrap();
+        assert!(expansion.captures().iter().all(Option::is_none));
+
+        while let Some(path) = expansion.next() {
+            let path = path.to_owned();
+            let expected = matcher::matches(&value, &path).unwrap();
+            let expected = expected
+                .captures
+                .iter()
+                .map(|capture| capture.as_ref().map(|capture| capture.text.as_str()))
+                .collect::<Vec<_>>();
+
+            assert_eq!(expansion.captures(), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn writes_paths_to_a_sink() {
+        let value = parse("a/(b,c)/[1..2]").unwrap();
+        let mut sink = Vec::new();
+
+        let written = write_paths(&value, &Limits::default(), &mut sink).unwrap();
+        assert_eq!(written, 4);
+        assert_eq!(sink, b"a/b/1\na/b/2\na/c/1\na/c/2\n");
+
+        let limits = Limits {
+            max_outputs: 3,
+            ..Limits::default()
+        };
+        let err = write_paths(&value, &limits, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
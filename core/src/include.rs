@@ -0,0 +1,251 @@
+use crate::pattern_file::{lines, Diagnostic};
+use logos::Span;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Clone)]
+pub struct IncludeOptions {
+    /// Directories searched, in order, for includes not found next to the including file
+    pub search_path: Vec<PathBuf>,
+}
+
+/// A pattern file together with every file it includes, directly or indirectly
+#[derive(Debug, Default)]
+pub struct Sources {
+    files: Vec<SourceFile>,
+    // Included file of each `@include(...)` line, keyed by file and line start
+    includes: HashMap<(usize, usize), usize>,
+    errors: Vec<Diagnostic>,
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String,
+    /// The file and include line that first pulled this file in, `None` for the root
+    pub included_from: Option<(usize, Span)>,
+}
+
+impl Sources {
+    /// Reads the pattern file at `path` and everything it includes.
+    ///
+    /// Only failing to read the root file is returned as an error, problems with includes are
+    /// kept as diagnostics and reported by [`crate::pattern_file::parse_sources`].
+    pub fn load(path: &Path, options: &IncludeOptions) -> io::Result<Self> {
+        let mut sources = Sources::default();
+        let source = fs::read_to_string(path)?;
+
+        sources.files.push(SourceFile {
+            path: path.to_path_buf(),
+            source,
+            included_from: None,
+        });
+
+        let mut stack = vec![canonical(path)];
+        let mut loaded = HashMap::from([(canonical(path), 0)]);
+        sources.load_includes(0, options, &mut stack, &mut loaded);
+
+        Ok(sources)
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    pub fn errors(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+
+    /// The file included by the `@include(...)` line starting at `line_start`
+    pub fn included(&self, file: usize, line_start: usize) -> Option<usize> {
+        self.includes.get(&(file, line_start)).copied()
+    }
+
+    /// The include lines leading to `file`, starting with the innermost one
+    pub fn include_chain(&self, file: usize) -> Vec<(usize, Span)> {
+        let mut chain = Vec::new();
+        let mut current = file;
+
+        while let Some((parent, span)) = &self.files[current].included_from {
+            chain.push((*parent, span.clone()));
+            current = *parent;
+        }
+
+        chain
+    }
+
+    fn load_includes(
+        &mut self,
+        file: usize,
+        options: &IncludeOptions,
+        stack: &mut Vec<PathBuf>,
+        loaded: &mut HashMap<PathBuf, usize>,
+    ) {
+        let includes = lines(&self.files[file].source)
+            .into_iter()
+            .filter_map(|line| {
+                let target = include_target(&self.files[file].source[line.span.clone()])?;
+                Some((line.span, target.to_owned()))
+            })
+            .collect::<Vec<_>>();
+
+        for (span, target) in includes {
+            let Some(path) = self.resolve(file, &target, options) else {
+                self.errors.push(Diagnostic {
+                    file,
+                    message: format!("Unable to find included file '{}'", target),
+                    span,
+                });
+                continue;
+            };
+
+            let key = canonical(&path);
+            if let Some(position) = stack.iter().position(|p| *p == key) {
+                let chain = stack[position..]
+                    .iter()
+                    .chain([&key])
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+
+                self.errors.push(Diagnostic {
+                    file,
+                    message: format!("Include cycle: {}", chain),
+                    span,
+                });
+                continue;
+            }
+
+            if let Some(&index) = loaded.get(&key) {
+                self.includes.insert((file, span.start), index);
+                continue;
+            }
+
+            let source = match fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(err) => {
+                    self.errors.push(Diagnostic {
+                        file,
+                        message: format!("Failed to read '{}': {}", path.display(), err),
+                        span,
+                    });
+                    continue;
+                }
+            };
+
+            let index = self.files.len();
+            self.files.push(SourceFile {
+                path,
+                source,
+                included_from: Some((file, span.clone())),
+            });
+            self.includes.insert((file, span.start), index);
+            loaded.insert(key.clone(), index);
+
+            stack.push(key);
+            self.load_includes(index, options, stack, loaded);
+            stack.pop();
+        }
+    }
+
+    // Includes are looked up next to the including file first, then along the search path
+    fn resolve(&self, file: usize, target: &str, options: &IncludeOptions) -> Option<PathBuf> {
+        let base_dir = self.files[file].path.parent().unwrap_or(Path::new(""));
+
+        [base_dir.to_path_buf()]
+            .iter()
+            .chain(&options.search_path)
+            .map(|dir| dir.join(target))
+            .find(|path| path.is_file())
+    }
+}
+
+/// The path of an `@include(path)` line, if the line is one
+pub fn include_target(line: &str) -> Option<&str> {
+    line.strip_prefix("@include(")?
+        .strip_suffix(')')
+        .map(str::trim)
+        .filter(|target| !target.is_empty())
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, TextInterpreter};
+    use crate::parser::ParseOptions;
+    use crate::pattern_file::parse_sources;
+    use tempfile::TempDir;
+
+    fn write_files(files: &[(&str, &str)]) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn includes_nest_under_the_including_line() {
+        let dir = write_files(&[
+            ("main.pf", "app\n  @include(shared/ci.pf)\n"),
+            ("shared/ci.pf", "@include(docs.pf)\nci.yml\n"),
+            ("lib/docs.pf", "docs/\n  index.md\n"),
+        ]);
+        let options = IncludeOptions {
+            search_path: vec![dir.path().join("lib")],
+        };
+
+        let sources = Sources::load(&dir.path().join("main.pf"), &options).unwrap();
+        let file = parse_sources(&sources, &ParseOptions::default()).unwrap();
+        let paths = file
+            .entries
+            .iter()
+            .flat_map(|entry| TextInterpreter.interpret(&entry.value).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            paths,
+            vec!["app/", "app/docs/", "app/docs/index.md", "app/ci.yml"]
+        );
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let dir = write_files(&[
+            ("a.pf", "@include(b.pf)\n"),
+            ("b.pf", "x\n@include(a.pf)\n"),
+        ]);
+
+        let sources = Sources::load(&dir.path().join("a.pf"), &IncludeOptions::default()).unwrap();
+        let errors = parse_sources(&sources, &ParseOptions::default()).unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].file, 1);
+        assert!(errors[0].message.starts_with("Include cycle: "));
+    }
+
+    #[test]
+    fn errors_in_includes_keep_the_chain() {
+        let dir = write_files(&[
+            ("a.pf", "top\n@include(b.pf)\n"),
+            ("b.pf", "@include(c.pf)\n"),
+            ("c.pf", "broken)\n"),
+        ]);
+
+        let sources = Sources::load(&dir.path().join("a.pf"), &IncludeOptions::default()).unwrap();
+        let errors = parse_sources(&sources, &ParseOptions::default()).unwrap_err();
+
+        assert_eq!(errors[0].file, 2);
+        assert_eq!(sources.include_chain(2), vec![(1, 0..14), (0, 4..18)]);
+    }
+}
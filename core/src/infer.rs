@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+
+/// Builds a pattern producing exactly the given paths, the reverse of the `TextInterpreter`.
+///
+/// Paths are split on '/', siblings that contain the same entries are merged into a single
+/// group and names are factored into shared prefixes and suffixes, with runs of consecutive
+/// numbers or characters written as ranges. A path ending in '/' stands for a directory.
+pub fn infer<S: AsRef<str>>(paths: &[S]) -> Result<String, String> {
+    let mut root = Node::default();
+
+    for path in paths {
+        let path = path.as_ref().trim_start_matches("./");
+        if path.is_empty() {
+            continue;
+        }
+        if path.contains(char::is_whitespace) {
+            return Err(format!(
+                "'{}' contains whitespace, which patterns can not express",
+                path
+            ));
+        }
+
+        root.insert(path.split('/'));
+    }
+
+    Ok(alternatives(root.children.iter().collect()).join(","))
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    // An empty name marks the directory itself, as in "dir/"
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn insert<'a>(&mut self, mut segments: impl Iterator<Item = &'a str>) {
+        if let Some(segment) = segments.next() {
+            self.children
+                .entry(segment.to_string())
+                .or_default()
+                .insert(segments);
+        }
+    }
+}
+
+// Every way to continue with one of `children`, to be written as one group
+fn alternatives(children: Vec<(&String, &Node)>) -> Vec<String> {
+    // Names of children, keyed by what follows them
+    let mut rests: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+
+    for (name, child) in children {
+        if child.children.is_empty() {
+            rests.entry(String::new()).or_default().push(name);
+            continue;
+        }
+
+        // A listed directory that also has entries is written on its own
+        let entries = child
+            .children
+            .iter()
+            .filter(|(name, _)| !name.is_empty())
+            .collect::<Vec<_>>();
+        if child.children.contains_key("") && !entries.is_empty() {
+            rests.entry("/".to_string()).or_default().push(name);
+        }
+
+        let rest = match entries.is_empty() {
+            true => "/".to_string(),
+            false => format!("/{}", group(&alternatives(entries))),
+        };
+        rests.entry(rest).or_default().push(name);
+    }
+
+    rests
+        .into_iter()
+        .map(|(rest, names)| format!("{}{}", compress(&names), rest))
+        .collect()
+}
+
+fn group(alternatives: &[String]) -> String {
+    match alternatives {
+        [single] => single.clone(),
+        _ => format!("({})", alternatives.join(",")),
+    }
+}
+
+// Writes a set of names as compactly as possible
+fn compress(names: &[&str]) -> String {
+    if let [name] = names {
+        return escape(name);
+    }
+
+    let prefix = common_prefix(names);
+    let suffix = common_suffix(names, prefix.len());
+
+    // Without a usable prefix and suffix every name is its own alternative
+    let (prefix, suffix) = [(prefix, suffix), (prefix, ""), ("", suffix), ("", "")]
+        .into_iter()
+        .find(|(prefix, suffix)| {
+            names
+                .iter()
+                .all(|name| name.len() > prefix.len() + suffix.len())
+        })
+        .unwrap_or(("", ""));
+
+    let middles = names
+        .iter()
+        .map(|name| &name[prefix.len()..name.len() - suffix.len()])
+        .collect::<Vec<_>>();
+
+    format!(
+        "{}{}{}",
+        escape(prefix),
+        group(&ranges(&middles)),
+        escape(suffix)
+    )
+}
+
+// Replaces runs of at least three consecutive numbers or characters by a range
+fn ranges(middles: &[&str]) -> Vec<String> {
+    let mut numbers = Vec::new();
+    let mut chars = Vec::new();
+    let mut others = Vec::new();
+
+    for middle in middles {
+        let mut single = middle.chars();
+        match (single.next(), single.next()) {
+            _ if is_number(middle) => numbers.push(middle.parse::<u32>().unwrap()),
+            (Some(c), None) if c.is_alphanumeric() => chars.push(c),
+            _ => others.push(escape(middle)),
+        }
+    }
+
+    numbers.sort_unstable();
+    chars.sort_unstable();
+
+    let mut result = Vec::new();
+    for (first, last) in runs(&numbers, |a, b| a.checked_add(1) == Some(b)) {
+        match last - first {
+            0 => result.push(first.to_string()),
+            1 => result.extend([first.to_string(), last.to_string()]),
+            _ => result.push(format!("[{}..{}]", first, last)),
+        }
+    }
+    for (first, last) in runs(&chars, |a, b| char::from_u32(a as u32 + 1) == Some(b)) {
+        match last as u32 - first as u32 {
+            0 => result.push(escape(&first.to_string())),
+            1 => result.extend([escape(&first.to_string()), escape(&last.to_string())]),
+            _ => result.push(format!("[{}..{}]", first, last)),
+        }
+    }
+    result.extend(others);
+
+    result
+}
+
+fn runs<T: Copy>(sorted: &[T], next: impl Fn(T, T) -> bool) -> Vec<(T, T)> {
+    let mut runs: Vec<(T, T)> = Vec::new();
+
+    for &item in sorted {
+        match runs.last_mut() {
+            Some((_, last)) if next(*last, item) => *last = item,
+            _ => runs.push((item, item)),
+        }
+    }
+
+    runs
+}
+
+// Numbers without leading zeroes, as produced by a number range
+fn is_number(text: &str) -> bool {
+    !text.is_empty()
+        && text.bytes().all(|b| b.is_ascii_digit())
+        && (text == "0" || !text.starts_with('0'))
+        && text.parse::<u32>().is_ok()
+}
+
+// The shared prefix of all names, not ending halfway through a number
+fn common_prefix<'a>(names: &[&'a str]) -> &'a str {
+    let first = names[0];
+    let mut end = first.len();
+    for name in &names[1..] {
+        end = first
+            .char_indices()
+            .zip(name.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((index, c), _)| index + c.len_utf8())
+            .min(end);
+    }
+
+    let prefix = &first[..end];
+    prefix.trim_end_matches(|c: char| c.is_ascii_digit())
+}
+
+// The shared suffix of all names after `skip` bytes, not starting halfway through a number
+fn common_suffix<'a>(names: &[&'a str], skip: usize) -> &'a str {
+    let first = &names[0][skip..];
+    let mut start = 0;
+    for name in names {
+        let name = &name[skip..];
+        let shared = first
+            .char_indices()
+            .rev()
+            .zip(name.chars().rev())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(first.len(), |((index, _), _)| index);
+        start = start.max(shared);
+    }
+
+    let suffix = &first[start..];
+    suffix.trim_start_matches(|c: char| c.is_ascii_digit())
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '(' | ')' | '[' | ']' | ',' | '@' | '$' | '~' | '\\' | '#'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, TextInterpreter};
+    use crate::parser::parse;
+
+    fn round_trip(paths: &[&str]) -> String {
+        let pattern = infer(paths).unwrap();
+
+        let mut expanded = TextInterpreter
+            .interpret(&parse(&pattern).unwrap())
+            .unwrap();
+        let mut expected = paths.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        expanded.sort();
+        expected.sort();
+        assert_eq!(expanded, expected, "pattern: {}", pattern);
+
+        pattern
+    }
+
+    #[test]
+    fn merges_identical_subtrees() {
+        let pattern = round_trip(&[
+            "config/Dev/app.json",
+            "config/Prod/app.json",
+            "config/Staging/app.json",
+            "README.md",
+        ]);
+
+        assert_eq!(pattern, "README.md,config/(Dev,Prod,Staging)/app.json");
+    }
+
+    #[test]
+    fn numeric_and_char_runs_become_ranges() {
+        let paths = (1..=12)
+            .map(|i| format!("fixtures/file_{}.txt", i))
+            .chain(["fixtures/file_x.txt".to_string()])
+            .collect::<Vec<_>>();
+        let paths = paths.iter().map(String::as_str).collect::<Vec<_>>();
+
+        assert_eq!(round_trip(&paths), "fixtures/file_([1..12],x).txt");
+        assert_eq!(round_trip(&["a.rs", "b.rs", "c.rs", "d.rs"]), "[a..d].rs");
+    }
+
+    #[test]
+    fn handles_directories_prefixes_and_special_characters() {
+        round_trip(&["src/", "src/main.rs", "docs/", "a.txt", "ab.txt"]);
+        round_trip(&["lang/C#/x", "lang/(weird),name", "01", "02", "10"]);
+    }
+
+    #[test]
+    fn rejects_whitespace() {
+        assert!(infer(&["My Documents/a"]).is_err());
+    }
+}
@@ -1,59 +1,218 @@
+use crate::expansion::Expansion;
+use crate::limits::{Budget, LimitError, Measure};
 use crate::parser::Value;
+use logos::Span;
+use std::fmt;
 
-pub trait Interpreter<'source, T> {
-    fn interpret(&self, value: &Value<'source>) -> T;
+/// Walks a pattern to compute something from it, such as its expansion or its size
+pub trait Interpreter<'source> {
+    type Output;
+    type Error;
+    /// State carried through an interpretation, and on to the next one when it is reused, such
+    /// as counters or lookups
+    type Context;
+
+    fn interpret_with(
+        &self,
+        value: &Value<'source>,
+        context: &mut Self::Context,
+    ) -> std::result::Result<Self::Output, Self::Error>;
+
+    /// Interprets `value` with a fresh context
+    fn interpret(&self, value: &Value<'source>) -> std::result::Result<Self::Output, Self::Error>
+    where
+        Self::Context: Default,
+    {
+        self.interpret_with(value, &mut Self::Context::default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpretError {
+    /// A `@name` that was not replaced by [`crate::macros::Definitions::expand`]
+    UnexpandedReference(String, Span),
+    /// The pattern produces more paths than can be counted
+    TooLarge,
+    /// Expanding the pattern would exceed the limits it is interpreted with
+    Limit(LimitError),
+}
+
+impl InterpretError {
+    /// The location of the error in the pattern, if it is known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            InterpretError::UnexpandedReference(_, span) => Some(span.clone()),
+            InterpretError::TooLarge => None,
+            InterpretError::Limit(err) => err.span.clone(),
+        }
+    }
 }
 
+impl fmt::Display for InterpretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpretError::UnexpandedReference(name, _) => {
+                write!(f, "Unknown reference '@{}'", name)
+            }
+            InterpretError::TooLarge => write!(f, "Pattern produces too many paths"),
+            InterpretError::Limit(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+type Result<T> = std::result::Result<T, InterpretError>;
+
+/// Expands a pattern into its paths, checking the budget before allocating any of them.
+///
+/// What was expanded is spent from the budget, so a budget reused for several patterns bounds
+/// all of them together. Without one nothing is limited.
 pub struct TextInterpreter;
 
-impl<'source> Interpreter<'source, Vec<String>> for TextInterpreter {
-    fn interpret(&self, value: &Value<'source>) -> Vec<String> {
-        match value {
-            Value::Text(s) => vec![s.to_string()],
-            Value::TextGroup(group) => group
-                .iter()
-                .flat_map(|value| self.interpret(value))
-                .collect(),
-            Value::ExpandableGroup(group) => group.iter().fold(Vec::new(), |current, expander| {
-                cartesian_product(current, self.interpret(expander))
-            }),
-            Value::CharRange(start, end) => (*start..=*end).map(|x| x.to_string()).collect(),
-            Value::NumberRange(start, end) => (*start..=*end).map(|x| x.to_string()).collect(),
+impl<'source> Interpreter<'source> for TextInterpreter {
+    type Output = Vec<String>;
+    type Error = InterpretError;
+    type Context = Budget;
+
+    fn interpret_with(&self, value: &Value<'source>, budget: &mut Budget) -> Result<Vec<String>> {
+        budget.check(value).map_err(InterpretError::Limit)?;
+        let mut expansion = Expansion::new(value)?;
+        let mut paths = Vec::with_capacity(Measure::of(value).outputs as usize);
+        while let Some(path) = expansion.next() {
+            paths.push(path.to_owned());
         }
+        budget.spend(value);
+
+        Ok(paths)
     }
 }
 
+/// Counts the paths of a pattern without expanding it, it needs no context
 pub struct SizeInterpreter;
-impl<'source> Interpreter<'source, u32> for SizeInterpreter {
-    fn interpret(&self, value: &Value<'source>) -> u32 {
-        match value {
-            Value::Text(_) => 1,
-            Value::TextGroup(group) => group.iter().map(|value| self.interpret(value)).sum(),
-            Value::ExpandableGroup(group) => group
-                .iter()
-                .fold(1, |current, expander| current * self.interpret(expander)),
-            Value::CharRange(start, end) => (*end as u32 - *start as u32) + 1,
-            Value::NumberRange(start, end) => (end - start) + 1,
+
+impl<'source> Interpreter<'source> for SizeInterpreter {
+    type Output = u32;
+    type Error = InterpretError;
+    type Context = ();
+
+    fn interpret_with(&self, value: &Value<'source>, _: &mut ()) -> Result<u32> {
+        count_paths(value)
+    }
+}
+
+fn count_paths(value: &Value) -> Result<u32> {
+    match value {
+        Value::Text(_) => Ok(1),
+        Value::TextGroup(group) => group.iter().try_fold(0u32, |total, value| {
+            total
+                .checked_add(count_paths(value)?)
+                .ok_or(InterpretError::TooLarge)
+        }),
+        // Members producing nothing are skipped, as they are when expanding
+        Value::ExpandableGroup(group) => {
+            let mut total = None;
+            for expander in group {
+                total = match (total, count_paths(expander)?) {
+                    (total, 0) => total,
+                    (None, size) => Some(size),
+                    (Some(total), size) => {
+                        Some(total.checked_mul(size).ok_or(InterpretError::TooLarge)?)
+                    }
+                };
+            }
+
+            Ok(total.unwrap_or(0))
         }
+        Value::CharRange(_, _) | Value::NumberRange(_, _) => {
+            u32::try_from(Measure::of(value).outputs).map_err(|_| InterpretError::TooLarge)
+        }
+        Value::Reference(name, span) => Err(InterpretError::UnexpandedReference(
+            name.to_string(),
+            span.clone(),
+        )),
     }
 }
 
-fn cartesian_product(left: Vec<String>, right: Vec<String>) -> Vec<String> {
-    // Check if either vector is empty
-    if left.is_empty() {
-        return right.to_vec();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Limits;
+    use crate::parser::parse;
+
+    #[test]
+    fn unexpanded_references_are_errors() {
+        let value = parse("a/@name").unwrap();
+
+        let err = TextInterpreter.interpret(&value).unwrap_err();
+        assert_eq!(
+            err,
+            InterpretError::UnexpandedReference("name".to_owned(), 2..7)
+        );
+        assert_eq!(err.to_string(), "Unknown reference '@name'");
+        assert!(SizeInterpreter.interpret(&value).is_err());
+    }
+
+    #[test]
+    fn size_reports_overflow() {
+        let value = parse("[0..4294967295]").unwrap();
+        assert_eq!(
+            SizeInterpreter.interpret(&value),
+            Err(InterpretError::TooLarge)
+        );
+
+        let value = parse("[0..65535][0..65535]").unwrap();
+        assert_eq!(
+            SizeInterpreter.interpret(&value),
+            Err(InterpretError::TooLarge)
+        );
+
+        let value = parse("(a,b)[1..3]()").unwrap();
+        assert_eq!(SizeInterpreter.interpret(&value), Ok(6));
     }
-    if right.is_empty() {
-        return left.to_vec();
+
+    #[test]
+    fn text_checks_limits_before_expanding() {
+        let value = parse("[1..1000][1..1000][1..1000]").unwrap();
+        let mut budget = Budget::new(Limits {
+            max_outputs: 1000,
+            ..Limits::default()
+        });
+
+        let err = TextInterpreter
+            .interpret_with(&value, &mut budget)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Produces 1000000000 paths, more than the limit of 1000"
+        );
     }
 
-    // Combine the elements of both vectors
-    let mut combined = Vec::new();
-    for l in &left {
-        for r in &right {
-            combined.push(format!("{}{}", l, r));
-        }
+    #[test]
+    fn text_spends_what_it_expands_from_the_budget() {
+        let mut budget = Budget::new(Limits {
+            max_outputs: 5,
+            ..Limits::default()
+        });
+
+        let paths = TextInterpreter.interpret_with(&parse("(a,b,c)").unwrap(), &mut budget);
+        assert_eq!(paths.unwrap().len(), 3);
+        assert_eq!(budget.outputs, 3);
+        assert_eq!(budget.limits.max_outputs, 5);
+
+        let err = TextInterpreter
+            .interpret_with(&parse("(d,e,f)").unwrap(), &mut budget)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Produces 6 paths, more than the limit of 5"
+        );
+        assert_eq!(budget.outputs, 3);
     }
 
-    combined
+    #[test]
+    fn text_is_unlimited_without_a_budget() {
+        let value = parse("[1..1001][1..1001]").unwrap();
+        assert_eq!(TextInterpreter.interpret(&value).unwrap().len(), 1_002_001);
+    }
 }
@@ -23,7 +23,74 @@ pub enum Token<'source> {
     #[token(",")]
     Comma,
 
-    // Excludes tokens defined above
-    #[regex(r"[^\s\.\,\[\]\(\)]+", |lex| lex.slice())]
+    // Directives such as `@file`, without the leading '@'
+    #[regex(r"@[A-Za-z_][A-Za-z0-9_\-]*", |lex| &lex.slice()[1..])]
+    Directive(&'source str),
+
+    // Environment variables, `$NAME` or `${NAME}`, without the surrounding syntax
+    #[regex(r"\$[A-Za-z_][A-Za-z0-9_]*", |lex| &lex.slice()[1..])]
+    #[regex(r"\$\{[A-Za-z_][A-Za-z0-9_]*\}", |lex| &lex.slice()[2..lex.slice().len() - 1])]
+    Variable(&'source str),
+
+    #[token("~")]
+    Tilde,
+
+    // A special character preceded by '\', without the backslash
+    #[regex(r"\\[$~@()\[\],.\\#]", |lex| &lex.slice()[1..])]
+    Escaped(&'source str),
+
+    // Excludes tokens defined above, a backslash is only kept when it escapes nothing, as at the
+    // end of a pattern. An '@' within a word, as in `user@host`, or not followed by a name, as in
+    // `icon@2x`, is text, as is a '$' not followed by a name, as in `price$`.
+    #[regex(r"([^\s\.\,\[\]\(\)@$~\\]|\\[^\s$~@()\[\],.\\#]|[A-Za-z0-9]@)+", |lex| lex.slice())]
+    #[token("@", |lex| lex.slice())]
+    #[token("$", |lex| lex.slice())]
+    #[token("\\", |lex| lex.slice())]
     Text(&'source str),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token<'_>> {
+        Token::lexer(source).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn at_signs_outside_references_are_text() {
+        assert_eq!(
+            tokens("icon@2x.png"),
+            [Token::Text("icon@2x"), Token::Dot, Token::Text("png")]
+        );
+        assert_eq!(tokens("user@host"), [Token::Text("user@host")]);
+        assert_eq!(
+            tokens("@2x/@envs"),
+            [
+                Token::Text("@"),
+                Token::Text("2x/"),
+                Token::Directive("envs")
+            ]
+        );
+    }
+
+    #[test]
+    fn dollars_outside_variables_are_text() {
+        assert_eq!(tokens("price$"), [Token::Text("price"), Token::Text("$")]);
+        assert_eq!(tokens("$5"), [Token::Text("$"), Token::Text("5")]);
+        assert_eq!(
+            tokens("${5}$HOME"),
+            [
+                Token::Text("$"),
+                Token::Text("{5}"),
+                Token::Variable("HOME")
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_backslashes_are_text() {
+        assert_eq!(tokens("a\\"), [Token::Text("a"), Token::Text("\\")]);
+        assert_eq!(tokens("a\\\\"), [Token::Text("a"), Token::Escaped("\\")]);
+    }
+}
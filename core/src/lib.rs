@@ -1,3 +1,14 @@
+pub mod brace;
+pub mod expansion;
+pub mod include;
+pub mod infer;
 pub mod interpreter;
 pub mod lexer;
+pub mod limits;
+pub mod macros;
+pub mod matcher;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 pub mod parser;
+pub mod pattern_file;
+pub mod resolver;
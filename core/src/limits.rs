@@ -0,0 +1,402 @@
+use crate::parser::Value;
+use logos::Span;
+use std::fmt;
+
+/// Bounds on what a pattern may produce, checked while parsing and again before expanding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The number of paths
+    pub max_outputs: u64,
+    /// The length of a single path in bytes
+    pub max_path_length: u64,
+    /// How deeply groups and ranges may be nested
+    pub max_depth: usize,
+    /// The length of all paths together in bytes
+    pub max_bytes: u64,
+    /// The texts, groups and ranges a pattern holds once its references are substituted
+    pub max_values: u64,
+}
+
+impl Limits {
+    pub const UNLIMITED: Limits = Limits {
+        max_outputs: u64::MAX,
+        max_path_length: u64::MAX,
+        max_depth: usize::MAX,
+        max_bytes: u64::MAX,
+        max_values: u64::MAX,
+    };
+
+    /// Checks what `value` would expand to, without expanding it
+    pub fn check(&self, value: &Value) -> Result<(), LimitError> {
+        let measure = Measure::of(value);
+
+        let exceeded = [
+            (Limit::Outputs, self.max_outputs, measure.outputs),
+            (Limit::PathLength, self.max_path_length, measure.longest),
+            (Limit::Depth, self.max_depth as u64, measure.depth),
+            (Limit::Bytes, self.max_bytes, measure.bytes),
+            (Limit::Values, self.max_values, measure.values),
+        ]
+        .into_iter()
+        .find(|(_, max, actual)| actual > max);
+
+        match exceeded {
+            Some((limit, max, actual)) => Err(LimitError {
+                limit,
+                max,
+                actual,
+                span: None,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_outputs: 1_000_000,
+            max_path_length: 4096,
+            max_depth: 128,
+            max_bytes: 256 * 1024 * 1024,
+            max_values: 1_000_000,
+        }
+    }
+}
+
+/// Limits shared by several expansions, with the paths and bytes they produced so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    pub limits: Limits,
+    pub outputs: u64,
+    pub bytes: u64,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Budget {
+            limits,
+            outputs: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Checks `value` against the limits, counting what was already produced towards the
+    /// number of paths and bytes
+    pub fn check(&self, value: &Value) -> Result<(), LimitError> {
+        self.limits.check(value)?;
+
+        let measure = Measure::of(value);
+        let exceeded = [
+            (
+                Limit::Outputs,
+                self.limits.max_outputs,
+                self.outputs.saturating_add(measure.outputs),
+            ),
+            (
+                Limit::Bytes,
+                self.limits.max_bytes,
+                self.bytes.saturating_add(measure.bytes),
+            ),
+        ]
+        .into_iter()
+        .find(|(_, max, actual)| actual > max);
+
+        match exceeded {
+            Some((limit, max, actual)) => Err(LimitError {
+                limit,
+                max,
+                actual,
+                span: None,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts the paths and bytes `value` expands to as produced
+    pub fn spend(&mut self, value: &Value) {
+        let measure = Measure::of(value);
+        self.outputs = self.outputs.saturating_add(measure.outputs);
+        self.bytes = self.bytes.saturating_add(measure.bytes);
+    }
+}
+
+/// Without any limits
+impl Default for Budget {
+    fn default() -> Self {
+        Budget::new(Limits::UNLIMITED)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Outputs,
+    PathLength,
+    Depth,
+    Bytes,
+    Values,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitError {
+    pub limit: Limit,
+    /// The configured maximum
+    pub max: u64,
+    /// What the pattern needs, saturating at `u64::MAX`
+    pub actual: u64,
+    /// The group, range or directive that exceeded the limit, when known
+    pub span: Option<Span>,
+}
+
+impl LimitError {
+    pub fn at(self, span: Span) -> Self {
+        LimitError {
+            span: Some(span),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (actual, max) = (self.actual, self.max);
+
+        match self.limit {
+            Limit::Outputs => write!(
+                f,
+                "Produces {} paths, more than the limit of {}",
+                actual, max
+            ),
+            Limit::PathLength => write!(
+                f,
+                "Produces paths of {} bytes, longer than the limit of {}",
+                actual, max
+            ),
+            Limit::Depth => write!(
+                f,
+                "Nests groups {} deep, deeper than the limit of {}",
+                actual, max
+            ),
+            Limit::Bytes => write!(
+                f,
+                "Produces {} bytes of paths, more than the limit of {}",
+                actual, max
+            ),
+            Limit::Values => write!(
+                f,
+                "Holds {} groups, ranges and texts, more than the limit of {}",
+                actual, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+/// What a value expands to, computed without expanding it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Measure {
+    pub outputs: u64,
+    pub longest: u64,
+    pub bytes: u64,
+    // Groups and ranges nested in the value, counting the value itself
+    pub depth: u64,
+    // Values in the value, counting the value itself
+    pub values: u64,
+}
+
+impl Measure {
+    const EMPTY: Measure = Measure {
+        outputs: 0,
+        longest: 0,
+        bytes: 0,
+        depth: 0,
+        values: 0,
+    };
+
+    pub fn of(value: &Value) -> Measure {
+        match value {
+            Value::Text(text) => Measure::text(text.len() as u64),
+            Value::Reference(name, _) => Measure::text(name.len() as u64 + 1),
+            Value::TextGroup(alternatives) => {
+                let mut measure =
+                    alternatives
+                        .iter()
+                        .map(Measure::of)
+                        .fold(Measure::EMPTY, |total, m| Measure {
+                            outputs: total.outputs.saturating_add(m.outputs),
+                            longest: total.longest.max(m.longest),
+                            bytes: total.bytes.saturating_add(m.bytes),
+                            depth: total.depth.max(m.depth),
+                            values: total.values.saturating_add(m.values),
+                        });
+                measure.depth += 1;
+                measure.values = measure.values.saturating_add(1);
+                measure
+            }
+            // Members producing nothing are skipped when expanded, like an empty group
+            Value::ExpandableGroup(members) => {
+                let measures = members.iter().map(Measure::of).collect::<Vec<_>>();
+                let values = measures
+                    .iter()
+                    .fold(1u64, |total, m| total.saturating_add(m.values));
+
+                let measure = measures
+                    .into_iter()
+                    .filter(|m| m.outputs > 0)
+                    .reduce(|total, m| Measure {
+                        outputs: total.outputs.saturating_mul(m.outputs),
+                        longest: total.longest.saturating_add(m.longest),
+                        bytes: total
+                            .bytes
+                            .saturating_mul(m.outputs)
+                            .saturating_add(m.bytes.saturating_mul(total.outputs)),
+                        depth: total.depth.max(m.depth),
+                        values: 0,
+                    })
+                    .unwrap_or(Measure::EMPTY);
+
+                Measure { values, ..measure }
+            }
+            Value::NumberRange(start, end) => {
+                let classes = (1..=10).map(|digits| {
+                    let first = match digits {
+                        1 => 0,
+                        _ => 10u64.pow(digits - 1),
+                    };
+                    (first, 10u64.pow(digits) - 1, digits as u64)
+                });
+
+                Measure::range(*start as u64, *end as u64, classes)
+            }
+            Value::CharRange(start, end) => {
+                let classes = [
+                    (0, 0x7F, 1),
+                    (0x80, 0x7FF, 2),
+                    (0x800, 0xD7FF, 3),
+                    (0xE000, 0xFFFF, 3),
+                    (0x10000, 0x10FFFF, 4),
+                ];
+
+                Measure::range(*start as u64, *end as u64, classes.into_iter())
+            }
+        }
+    }
+
+    fn text(length: u64) -> Measure {
+        Measure {
+            outputs: 1,
+            longest: length,
+            bytes: length,
+            depth: 0,
+            values: 1,
+        }
+    }
+
+    // A range over `start..=end`, every item in a class `(first, last, length)` has that length
+    fn range(start: u64, end: u64, classes: impl Iterator<Item = (u64, u64, u64)>) -> Measure {
+        let mut measure = Measure {
+            depth: 1,
+            values: 1,
+            ..Measure::EMPTY
+        };
+
+        for (first, last, length) in classes {
+            let (first, last) = (first.max(start), last.min(end));
+            if first <= last {
+                measure.outputs += last - first + 1;
+                measure.longest = length;
+                measure.bytes += (last - first + 1) * length;
+            }
+        }
+
+        measure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, TextInterpreter};
+    use crate::parser::{parse, parse_with, ParseOptions};
+
+    fn measure(pattern: &str) -> Measure {
+        let value = parse(pattern).unwrap();
+        let paths = TextInterpreter.interpret(&value).unwrap();
+        let measure = Measure::of(&value);
+
+        assert_eq!(measure.outputs, paths.len() as u64, "{}", pattern);
+        assert_eq!(
+            measure.longest,
+            paths.iter().map(|p| p.len() as u64).max().unwrap_or(0),
+            "{}",
+            pattern
+        );
+        assert_eq!(
+            measure.bytes,
+            paths.iter().map(|p| p.len() as u64).sum::<u64>(),
+            "{}",
+            pattern
+        );
+
+        measure
+    }
+
+    #[test]
+    fn measure_matches_expansion() {
+        measure("src/(a,bcd)_[8..12].rs");
+        measure("[x..ä]()(,)x");
+        assert_eq!(measure("a(b,(c,(d)))").depth, 4);
+        assert_eq!(measure("a(b,[1..2])").values, 9);
+    }
+
+    #[test]
+    fn parser_reports_the_group_exceeding_a_limit() {
+        let options = |limits| ParseOptions {
+            limits,
+            ..ParseOptions::default()
+        };
+
+        let limits = Limits {
+            max_outputs: 50,
+            ..Limits::default()
+        };
+        let (msg, span) = parse_with("a/(b,[1..99])/c", &options(limits)).unwrap_err();
+        assert_eq!(msg, "Produces 99 paths, more than the limit of 50");
+        assert_eq!(span, 5..12);
+
+        let limits = Limits {
+            max_depth: 3,
+            ..Limits::default()
+        };
+        assert!(parse_with("((a))", &options(limits)).is_ok());
+        let (msg, span) = parse_with("(((a)))", &options(limits)).unwrap_err();
+        assert_eq!(msg, "Nests groups 4 deep, deeper than the limit of 3");
+        assert_eq!(span, 2..3);
+    }
+
+    #[test]
+    fn check_reports_first_exceeded_limit() {
+        let value = parse("[1..100]/(a,b)").unwrap();
+        let limits = Limits {
+            max_outputs: 100,
+            ..Limits::default()
+        };
+
+        let err = limits.check(&value).unwrap_err();
+        assert_eq!((err.limit, err.max, err.actual), (Limit::Outputs, 100, 200));
+        assert_eq!(
+            err.to_string(),
+            "Produces 200 paths, more than the limit of 100"
+        );
+
+        let limits = Limits {
+            max_path_length: 4,
+            ..Limits::default()
+        };
+        assert_eq!(limits.check(&value).unwrap_err().limit, Limit::PathLength);
+
+        let value = parse("[0..4294967295][0..4294967295]").unwrap();
+        assert!(Limits::UNLIMITED.check(&value).is_ok());
+    }
+}
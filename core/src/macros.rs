@@ -0,0 +1,222 @@
+use crate::limits::{Limit, LimitError, Limits, Measure};
+use crate::parser::Value;
+use logos::Span;
+use std::collections::HashMap;
+
+type Error = (String, Span);
+type Result<T> = std::result::Result<T, Error>;
+
+/// Named fragments that can be referenced from a pattern as `@name`
+#[derive(Debug, Default, Clone)]
+pub struct Definitions<'source> {
+    fragments: HashMap<String, Value<'source>>,
+}
+
+impl<'source> Definitions<'source> {
+    pub fn new() -> Self {
+        Definitions {
+            fragments: HashMap::new(),
+        }
+    }
+
+    /// Adds a fragment, returning the one it replaced
+    pub fn define(&mut self, name: &str, value: Value<'source>) -> Option<Value<'source>> {
+        self.fragments.insert(name.to_owned(), value)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value<'source>> {
+        self.fragments.get(name)
+    }
+
+    /// Copies every fragment, so the definitions can outlive the source they were parsed from
+    pub fn into_owned(self) -> Definitions<'static> {
+        Definitions {
+            fragments: self
+                .fragments
+                .into_iter()
+                .map(|(name, value)| (name, value.into_owned()))
+                .collect(),
+        }
+    }
+
+    /// Replaces every reference in `value` with its definition.
+    ///
+    /// Definitions may reference each other, a definition that ends up referencing itself is
+    /// reported at the reference closing the cycle.
+    pub fn expand(&self, value: &Value<'source>) -> Result<Value<'source>> {
+        self.expand_with(value, &Limits::UNLIMITED)
+    }
+
+    /// Like [`Definitions::expand`], failing at the first reference that makes a definition or
+    /// the result hold more values than `limits` allow, before substituting it any further
+    pub fn expand_with(&self, value: &Value<'source>, limits: &Limits) -> Result<Value<'source>> {
+        let mut expander = Expander {
+            definitions: self,
+            limits,
+            expanded: HashMap::new(),
+            stack: Vec::new(),
+        };
+
+        let expanded = expander.expand(value)?;
+        expander.check(&expanded, 0..0)?;
+        Ok(expanded)
+    }
+}
+
+// Expands every definition only once, references to it get a copy of the expansion
+struct Expander<'definitions, 'source> {
+    definitions: &'definitions Definitions<'source>,
+    limits: &'definitions Limits,
+    expanded: HashMap<String, Value<'source>>,
+    stack: Vec<String>,
+}
+
+impl<'source> Expander<'_, 'source> {
+    fn expand(&mut self, value: &Value<'source>) -> Result<Value<'source>> {
+        match value {
+            Value::Reference(name, span) => self.reference(name, span),
+            Value::TextGroup(group) => Ok(Value::TextGroup(
+                group
+                    .iter()
+                    .map(|value| self.expand(value))
+                    .collect::<Result<_>>()?,
+            )),
+            Value::ExpandableGroup(group) => {
+                let mut members = Vec::with_capacity(group.len());
+                for member in group {
+                    let expanded = self.expand(member)?;
+                    match member {
+                        Value::Reference(_, _) => members.extend(expanded.into_sequence()),
+                        _ => members.push(expanded),
+                    }
+                }
+
+                Ok(Value::ExpandableGroup(members))
+            }
+            _ => Ok(value.clone()),
+        }
+    }
+
+    fn reference(&mut self, name: &str, span: &Span) -> Result<Value<'source>> {
+        if let Some(expanded) = self.expanded.get(name) {
+            return Ok(expanded.clone());
+        }
+
+        if self.stack.iter().any(|n| n == name) {
+            let chain = self
+                .stack
+                .iter()
+                .map(String::as_str)
+                .chain([name])
+                .map(|n| format!("@{}", n))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            return Err((format!("Recursive definition: {}", chain), span.clone()));
+        }
+
+        let Some(definition) = self.definitions.fragments.get(name) else {
+            return Err((format!("Unknown reference '@{}'", name), span.clone()));
+        };
+
+        self.stack.push(name.to_owned());
+        let expanded = self.expand(definition);
+        self.stack.pop();
+
+        let expanded = expanded?;
+        self.check(&expanded, span.clone())?;
+        self.expanded.insert(name.to_owned(), expanded.clone());
+        Ok(expanded)
+    }
+
+    fn check(&self, value: &Value, span: Span) -> Result<()> {
+        let values = Measure::of(value).values;
+        if values <= self.limits.max_values {
+            return Ok(());
+        }
+
+        let err = LimitError {
+            limit: Limit::Values,
+            max: self.limits.max_values,
+            actual: values,
+            span: Some(span.clone()),
+        };
+        Err((err.to_string(), span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, TextInterpreter};
+    use crate::parser::{parse, parse_definition};
+
+    fn definitions<'a>(sources: &[&'a str]) -> Definitions<'a> {
+        let mut definitions = Definitions::new();
+        for source in sources {
+            let (name, value) = parse_definition(source).unwrap();
+            definitions.define(name, value);
+        }
+
+        definitions
+    }
+
+    #[test]
+    fn expand_substitutes_nested_definitions() {
+        let definitions = definitions(&["@envs = (Dev,Prod)", "@config = @envs.json"]);
+        let value = definitions
+            .expand(&parse("config/@config").unwrap())
+            .unwrap();
+
+        assert_eq!(
+            TextInterpreter.interpret(&value).unwrap(),
+            vec!["config/Dev.json", "config/Prod.json"]
+        );
+    }
+
+    #[test]
+    fn expand_reports_unknown_reference() {
+        let (msg, span) = Definitions::new()
+            .expand(&parse("a/@missing/b").unwrap())
+            .unwrap_err();
+
+        assert_eq!(msg, "Unknown reference '@missing'");
+        assert_eq!(span, 2..10);
+    }
+
+    #[test]
+    fn expand_detects_cycles() {
+        let definitions = definitions(&["@a = x/@b", "@b = (y,@a)"]);
+        let (msg, _) = definitions.expand(&parse("@a").unwrap()).unwrap_err();
+
+        assert_eq!(msg, "Recursive definition: @a -> @b -> @a");
+    }
+
+    #[test]
+    fn expand_with_limits_stops_growing_definitions() {
+        let mut sources = (0..40)
+            .map(|i| format!("@d{} = (@d{},@d{})", i, i + 1, i + 1))
+            .collect::<Vec<_>>();
+        sources.push("@d40 = x".to_owned());
+        let definitions = definitions(&sources.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let limits = Limits {
+            max_values: 1000,
+            ..Limits::default()
+        };
+        let (msg, span) = definitions
+            .expand_with(&parse("a/@d0").unwrap(), &limits)
+            .unwrap_err();
+        assert_eq!(
+            msg,
+            "Holds 1023 groups, ranges and texts, more than the limit of 1000"
+        );
+        // The reference within the definition that grew too large
+        assert_eq!(span, 8..12);
+
+        let value = definitions
+            .expand_with(&parse("@d36").unwrap(), &limits)
+            .unwrap();
+        assert_eq!(TextInterpreter.interpret(&value).unwrap().len(), 16);
+    }
+}
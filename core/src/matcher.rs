@@ -0,0 +1,300 @@
+use crate::parser::Value;
+
+/// The value chosen for a group or range when matching a path
+#[derive(Debug, Clone, PartialEq)]
+pub enum Choice {
+    /// Index of the alternative within its group
+    Alternative(usize),
+    Char(char),
+    Number(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    /// The part of the path produced by the group or range
+    pub text: String,
+    pub choice: Choice,
+}
+
+/// The captures of a successful match.
+///
+/// Every group and range in the pattern, except the pattern itself, gets a slot numbered in
+/// the order in which they are written. Slots of groups and ranges within alternatives that
+/// were not chosen stay empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub captures: Vec<Option<Capture>>,
+}
+
+impl Match {
+    pub fn get(&self, index: usize) -> Option<&Capture> {
+        self.captures.get(index).and_then(Option::as_ref)
+    }
+
+    /// Replaces every `{n}` in `template` with the text of capture `n`, counting from 1
+    pub fn substitute(&self, template: &str) -> Result<String, String> {
+        let mut result = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(open) = rest.find('{') {
+            result.push_str(&rest[..open]);
+            rest = &rest[open..];
+
+            let Some(close) = rest.find('}') else { break };
+            let Ok(index) = rest[1..close].parse::<usize>() else {
+                result.push('{');
+                rest = &rest[1..];
+                continue;
+            };
+
+            match index.checked_sub(1).and_then(|index| self.get(index)) {
+                Some(capture) => result.push_str(&capture.text),
+                None => return Err(format!("Capture {{{}}} did not match anything", index)),
+            }
+            rest = &rest[close + 1..];
+        }
+
+        result.push_str(rest);
+        Ok(result)
+    }
+}
+
+/// Checks whether `value` would produce `path`, without expanding the pattern.
+///
+/// References must have been expanded beforehand, they never match.
+pub fn matches(value: &Value, path: &str) -> Option<Match> {
+    let mut matcher = Matcher {
+        path,
+        captures: vec![None; captures(value)],
+    };
+
+    let matched = matcher.match_value(value, 0, 0, false, &mut |matcher, end| {
+        end == matcher.path.len()
+    });
+
+    match matched {
+        true => Some(Match {
+            captures: matcher.captures,
+        }),
+        false => None,
+    }
+}
+
+/// The number of capture slots of a pattern
+pub fn captures(value: &Value) -> usize {
+    capture_count(value) - is_captured(value) as usize
+}
+
+// The number of groups and ranges in `value`, including itself
+fn capture_count(value: &Value) -> usize {
+    match value {
+        Value::TextGroup(group) => 1 + group.iter().map(capture_count).sum::<usize>(),
+        Value::ExpandableGroup(group) => group.iter().map(capture_count).sum(),
+        Value::CharRange(_, _) | Value::NumberRange(_, _) => 1,
+        Value::Text(_) | Value::Reference(_, _) => 0,
+    }
+}
+
+type Continuation<'a, 'path> = dyn FnMut(&mut Matcher<'path>, usize) -> bool + 'a;
+
+struct Matcher<'path> {
+    path: &'path str,
+    captures: Vec<Option<Capture>>,
+}
+
+impl<'path> Matcher<'path> {
+    // Matches `value` at `start`, calling `next` with the end of every way it can match until
+    // `next` accepts one. `slot` is the first capture slot used by `value`, which is its own
+    // slot when `captured` is set.
+    fn match_value(
+        &mut self,
+        value: &Value,
+        start: usize,
+        slot: usize,
+        captured: bool,
+        next: &mut Continuation<'_, 'path>,
+    ) -> bool {
+        // Slots of nested groups follow the slot of their parent
+        let first_child = slot + captured as usize;
+        let slot = captured.then_some(slot);
+
+        match value {
+            Value::Text(text) => {
+                self.path[start..].starts_with(text.as_ref()) && next(self, start + text.len())
+            }
+            Value::ExpandableGroup(members) => {
+                self.match_sequence(members, start, first_child, next)
+            }
+            // An empty group leaves the rest of the sequence untouched when expanded
+            Value::TextGroup(alternatives) if alternatives.is_empty() => next(self, start),
+            Value::TextGroup(alternatives) => {
+                let mut child = first_child;
+
+                for (index, alternative) in alternatives.iter().enumerate() {
+                    let captured = is_captured(alternative);
+                    let matched =
+                        self.match_value(alternative, start, child, captured, &mut |m, end| {
+                            m.capture(slot, start, end, Choice::Alternative(index), next)
+                        });
+                    if matched {
+                        return true;
+                    }
+
+                    child += capture_count(alternative);
+                }
+
+                false
+            }
+            Value::CharRange(first, last) => match self.path[start..].chars().next() {
+                Some(c) if (*first..=*last).contains(&c) => {
+                    self.capture(slot, start, start + c.len_utf8(), Choice::Char(c), next)
+                }
+                _ => false,
+            },
+            Value::NumberRange(first, last) => {
+                let digits = self.path[start..]
+                    .bytes()
+                    .take_while(u8::is_ascii_digit)
+                    .count();
+
+                // Numbers are written without leading zeroes, try the longest candidate first
+                for end in (start + 1..=start + digits).rev() {
+                    let text = &self.path[start..end];
+                    if text.len() > 1 && text.starts_with('0') {
+                        continue;
+                    }
+
+                    let matched = match text.parse::<u32>() {
+                        Ok(number) if (*first..=*last).contains(&number) => {
+                            self.capture(slot, start, end, Choice::Number(number), next)
+                        }
+                        _ => false,
+                    };
+                    if matched {
+                        return true;
+                    }
+                }
+
+                false
+            }
+            Value::Reference(_, _) => false,
+        }
+    }
+
+    fn match_sequence(
+        &mut self,
+        members: &[Value],
+        start: usize,
+        slot: usize,
+        next: &mut Continuation<'_, 'path>,
+    ) -> bool {
+        let Some((first, rest)) = members.split_first() else {
+            return next(self, start);
+        };
+
+        let rest_slot = slot + capture_count(first);
+
+        self.match_value(first, start, slot, is_captured(first), &mut |m, end| {
+            m.match_sequence(rest, end, rest_slot, next)
+        })
+    }
+
+    // Records the capture before continuing, and clears it again when the continuation fails
+    fn capture(
+        &mut self,
+        slot: Option<usize>,
+        start: usize,
+        end: usize,
+        choice: Choice,
+        next: &mut Continuation<'_, 'path>,
+    ) -> bool {
+        let Some(slot) = slot else {
+            return next(self, end);
+        };
+
+        self.captures[slot] = Some(Capture {
+            text: self.path[start..end].to_owned(),
+            choice,
+        });
+
+        let matched = next(self, end);
+        if !matched {
+            self.captures[slot] = None;
+        }
+
+        matched
+    }
+}
+
+pub(crate) fn is_captured(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::TextGroup(_) | Value::CharRange(_, _) | Value::NumberRange(_, _)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn texts(m: &Match) -> Vec<Option<&str>> {
+        m.captures
+            .iter()
+            .map(|c| c.as_ref().map(|c| c.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn matches_without_expanding() {
+        let value = parse("services/[0..99999]/(api,worker).toml").unwrap();
+
+        let m = matches(&value, "services/4711/worker.toml").unwrap();
+        assert_eq!(m.get(0).unwrap().choice, Choice::Number(4711));
+        assert_eq!(m.get(1).unwrap().choice, Choice::Alternative(1));
+
+        assert!(matches(&value, "services/100000/api.toml").is_none());
+        assert!(matches(&value, "services/007/api.toml").is_none());
+        assert!(matches(&value, "services/7/api.tom").is_none());
+
+        // More paths than an expansion is allowed by default
+        let value = parse("x/[0..9999999]").unwrap();
+        assert_eq!(
+            matches(&value, "x/5").unwrap().get(0).unwrap().choice,
+            Choice::Number(5)
+        );
+    }
+
+    #[test]
+    fn nested_groups_capture_in_written_order() {
+        let value = parse("src/(a,b(x,y))_[a..c].rs").unwrap();
+
+        let m = matches(&value, "src/by_c.rs").unwrap();
+        assert_eq!(texts(&m), vec![Some("by"), Some("y"), Some("c")]);
+
+        let m = matches(&value, "src/a_a.rs").unwrap();
+        assert_eq!(texts(&m), vec![Some("a"), None, Some("a")]);
+    }
+
+    #[test]
+    fn backtracks_into_earlier_alternatives() {
+        let value = parse("(a,ab)(bc,c)[1..20][1..9]").unwrap();
+
+        let m = matches(&value, "abc111").unwrap();
+        assert_eq!(
+            texts(&m),
+            vec![Some("a"), Some("bc"), Some("11"), Some("1")]
+        );
+        assert_eq!(captures(&value), 4);
+    }
+
+    #[test]
+    fn substitute_uses_one_based_captures() {
+        let value = parse("src/(a,b)_(old).rs").unwrap();
+        let m = matches(&value, "src/b_old.rs").unwrap();
+
+        assert_eq!(m.substitute("src/{1}/{2}.rs").unwrap(), "src/b/old.rs");
+        assert_eq!(m.substitute("{x}/{1}{").unwrap(), "{x}/b{");
+        assert!(m.substitute("{3}").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::interpreter::{InterpretError, Interpreter};
+use crate::limits::{Budget, Measure};
+use crate::parser::Value;
+use rayon::prelude::*;
+use std::fmt::Write;
+
+/// Expands a pattern like [`crate::interpreter::TextInterpreter`], using every thread of the
+/// rayon pool.
+///
+/// Every path has an index in the order the text interpreter produces them, and any path can be
+/// written from its index alone. The index space is split into chunks of at least
+/// [`MIN_CHUNK`] paths, which are expanded independently and collected in order.
+pub struct ParallelTextInterpreter;
+
+/// The fewest paths a thread is given, smaller patterns are expanded on the calling thread
+pub const MIN_CHUNK: usize = 4096;
+
+impl<'source> Interpreter<'source> for ParallelTextInterpreter {
+    type Output = Vec<String>;
+    type Error = InterpretError;
+    type Context = Budget;
+
+    fn interpret_with(
+        &self,
+        value: &Value<'source>,
+        budget: &mut Budget,
+    ) -> Result<Vec<String>, InterpretError> {
+        budget.check(value).map_err(InterpretError::Limit)?;
+
+        let counted = Counted::new(value)?;
+        let count = usize::try_from(counted.count).map_err(|_| InterpretError::TooLarge)?;
+
+        let paths = (0..count)
+            .into_par_iter()
+            .with_min_len(MIN_CHUNK)
+            .map(|index| {
+                let mut path = String::new();
+                counted.write(index as u64, &mut path);
+                path
+            })
+            .collect();
+        budget.spend(value);
+
+        Ok(paths)
+    }
+}
+
+// A value along with the number of paths it and its members produce
+struct Counted<'value, 'source> {
+    value: &'value Value<'source>,
+    count: u64,
+    members: Vec<Counted<'value, 'source>>,
+    // For sequences, the number of combinations of the members after each member
+    strides: Vec<u64>,
+}
+
+impl<'value, 'source> Counted<'value, 'source> {
+    fn new(value: &'value Value<'source>) -> Result<Self, InterpretError> {
+        let mut counted = Counted {
+            value,
+            count: 0,
+            members: Vec::new(),
+            strides: Vec::new(),
+        };
+
+        match value {
+            Value::Text(_) => counted.count = 1,
+            Value::TextGroup(alternatives) => {
+                counted.members = alternatives
+                    .iter()
+                    .map(Counted::new)
+                    .collect::<Result<_, _>>()?;
+                counted.count = counted.members.iter().map(|m| m.count).sum();
+            }
+            // Members producing nothing are skipped, as they are by the text interpreter
+            Value::ExpandableGroup(members) => {
+                counted.members = members
+                    .iter()
+                    .map(Counted::new)
+                    .filter(|m| !matches!(m, Ok(m) if m.count == 0))
+                    .collect::<Result<_, _>>()?;
+
+                let mut stride = 1u64;
+                for member in counted.members.iter().rev() {
+                    counted.strides.push(stride);
+                    stride = stride
+                        .checked_mul(member.count)
+                        .ok_or(InterpretError::TooLarge)?;
+                }
+                counted.strides.reverse();
+                counted.count = match counted.members.is_empty() {
+                    true => 0,
+                    false => stride,
+                };
+            }
+            Value::CharRange(_, _) | Value::NumberRange(_, _) => {
+                counted.count = Measure::of(value).outputs
+            }
+            Value::Reference(name, span) => {
+                return Err(InterpretError::UnexpandedReference(
+                    name.to_string(),
+                    span.clone(),
+                ))
+            }
+        }
+
+        Ok(counted)
+    }
+
+    // Appends the path at `index`, which must be below `count`
+    fn write(&self, mut index: u64, path: &mut String) {
+        match self.value {
+            Value::Text(text) => path.push_str(text),
+            Value::TextGroup(_) => {
+                for member in &self.members {
+                    if index < member.count {
+                        return member.write(index, path);
+                    }
+                    index -= member.count;
+                }
+            }
+            // The last member changes fastest, like the digits of a number
+            Value::ExpandableGroup(_) => {
+                for (member, stride) in self.members.iter().zip(&self.strides) {
+                    member.write(index / stride % member.count, path);
+                }
+            }
+            Value::CharRange(start, _) => {
+                let mut c = *start as u32 + index as u32;
+                // Surrogates are not characters, ranges skip over them
+                if (*start as u32) < 0xD800 && c >= 0xD800 {
+                    c += 0x800;
+                }
+                path.extend(char::from_u32(c));
+            }
+            Value::NumberRange(start, _) => {
+                let _ = write!(path, "{}", *start as u64 + index);
+            }
+            Value::Reference(_, _) => unreachable!("references are rejected when counting"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::TextInterpreter;
+    use crate::limits::Limits;
+    use crate::parser::parse;
+
+    #[test]
+    fn matches_sequential_expansion_in_order() {
+        for pattern in [
+            "src/(a,bcd)_[8..12].rs",
+            "[x..ä]()(,)x/(1,(2,[3..4]))",
+            "fixtures/[1..40]/(a,b,c)/[a..z][0..9].txt",
+        ] {
+            let value = parse(pattern).unwrap();
+
+            assert_eq!(
+                ParallelTextInterpreter.interpret(&value).unwrap(),
+                TextInterpreter.interpret(&value).unwrap(),
+                "{}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_references_and_limits() {
+        assert!(ParallelTextInterpreter
+            .interpret(&parse("a/@name").unwrap())
+            .is_err());
+
+        let mut budget = Budget::new(Limits {
+            max_outputs: 10,
+            ..Limits::default()
+        });
+        let value = parse("[1..11]").unwrap();
+        assert!(ParallelTextInterpreter
+            .interpret_with(&value, &mut budget)
+            .is_err());
+    }
+}
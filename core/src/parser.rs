@@ -1,29 +1,177 @@
 use crate::lexer::Token;
+use crate::limits::{Limit, LimitError, Limits};
 use crate::parser::Value::{CharRange, ExpandableGroup, NumberRange};
+use crate::resolver::{Environment, FileListResolver, ListResolver};
 use logos::{Lexer, Logos, Span};
+use std::borrow::Cow;
 use std::mem::take;
 
 type Error = (String, Span);
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum Value<'source> {
     ExpandableGroup(Vec<Value<'source>>),
     TextGroup(Vec<Value<'source>>),
-    Text(&'source str),
+    Text(Cow<'source, str>),
     CharRange(char, char),
     NumberRange(u32, u32),
+    /// A named fragment, `@name`, substituted by [`crate::macros::Definitions::expand`]
+    Reference(Cow<'source, str>, Span),
+}
+
+/// A pattern that does not borrow from its source, see [`Value::into_owned`]
+pub type OwnedValue = Value<'static>;
+
+impl<'source> Value<'source> {
+    /// The members of this value when placed in a sequence, unwrapping single alternative groups
+    /// so splicing a parsed pattern into another one does not introduce a group of its own
+    pub fn into_sequence(self) -> Vec<Value<'source>> {
+        match self {
+            Value::TextGroup(mut alternatives) if alternatives.len() == 1 => {
+                match alternatives.pop() {
+                    Some(Value::ExpandableGroup(members)) => members,
+                    Some(other) => vec![other],
+                    None => Vec::new(),
+                }
+            }
+            Value::ExpandableGroup(members) => members,
+            other => vec![other],
+        }
+    }
+
+    /// Copies any text borrowed from the source, so the value can outlive it
+    pub fn into_owned(self) -> OwnedValue {
+        let owned =
+            |values: Vec<Value<'source>>| values.into_iter().map(Value::into_owned).collect();
+
+        match self {
+            Value::ExpandableGroup(members) => Value::ExpandableGroup(owned(members)),
+            Value::TextGroup(alternatives) => Value::TextGroup(owned(alternatives)),
+            Value::Text(text) => Value::Text(Cow::Owned(text.into_owned())),
+            Value::CharRange(start, end) => Value::CharRange(start, end),
+            Value::NumberRange(start, end) => Value::NumberRange(start, end),
+            Value::Reference(name, span) => Value::Reference(Cow::Owned(name.into_owned()), span),
+        }
+    }
+}
+
+/// Directive names that can not be used for definitions
+pub const RESERVED_NAMES: [&str; 2] = ["file", "include"];
+
+pub struct ParseOptions {
+    /// Supplies the alternatives of `@file(...)` constructs
+    pub resolver: Box<dyn ListResolver>,
+    /// Resolves `$NAME`, `${NAME}` and `~`, these are kept as literal text when `None`
+    pub environment: Option<Box<dyn Environment>>,
+    /// Fail on unset variables instead of replacing them with nothing
+    pub strict: bool,
+    /// Checked for every group, range and `@file(...)` as soon as it is parsed. Unlimited by
+    /// default, as matching or converting a pattern never expands it
+    pub limits: Limits,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            resolver: Box::new(FileListResolver::default()),
+            environment: None,
+            strict: false,
+            limits: Limits::UNLIMITED,
+        }
+    }
 }
 
-pub fn parse(pattern: &str) -> Result<Value> {
+pub fn parse(pattern: &str) -> Result<Value<'_>> {
+    parse_with(pattern, &ParseOptions::default())
+}
+
+pub fn parse_with<'source>(
+    pattern: &'source str,
+    options: &ParseOptions,
+) -> Result<Value<'source>> {
     let mut lexer = Token::lexer(pattern);
 
-    parse_group(&mut lexer, false)
+    let value = parse_group(&mut lexer, options, false, 1)?;
+    check_limits(options, &value, 0..pattern.len())?;
+    Ok(value)
+}
+
+/// Parses a named fragment of the form `@name = pattern`
+pub fn parse_definition(source: &str) -> Result<(&str, Value<'_>)> {
+    parse_definition_with(source, &ParseOptions::default())
+}
+
+pub fn parse_definition_with<'source>(
+    source: &'source str,
+    options: &ParseOptions,
+) -> Result<(&'source str, Value<'source>)> {
+    parse_definition_in(source, 0..source.len(), options)
+}
+
+/// Parses the pattern within `span` of a larger source, errors are reported relative to `source`
+pub(crate) fn parse_in<'source>(
+    source: &'source str,
+    span: Span,
+    options: &ParseOptions,
+) -> Result<Value<'source>> {
+    let mut lexer = Token::lexer(&source[..span.end]);
+    lexer.bump(span.start);
+
+    let value = parse_group(&mut lexer, options, false, 1)?;
+    check_limits(options, &value, span)?;
+    Ok(value)
+}
+
+/// Parses the definition within `span` of a larger source, errors are reported relative to `source`
+pub(crate) fn parse_definition_in<'source>(
+    source: &'source str,
+    span: Span,
+    options: &ParseOptions,
+) -> Result<(&'source str, Value<'source>)> {
+    let line = &source[span.clone()];
+    let start = span.start + line.len() - line.trim_start().len();
+    let end = span.start + line.trim_end().len();
+
+    let Some(equals) = source[start..end].find('=').map(|i| start + i) else {
+        return Err(("Expected '=' in definition".to_owned(), start..end));
+    };
+
+    let name = source[start..equals].trim();
+    let name_span = start..equals;
+    let Some(name) = name.strip_prefix('@') else {
+        return Err((
+            "Expected definition to start with '@'".to_owned(),
+            name_span,
+        ));
+    };
+    if !is_valid_name(name) {
+        return Err((format!("Invalid definition name '{}'", name), name_span));
+    }
+    if RESERVED_NAMES.contains(&name) {
+        return Err((format!("'@{}' is a reserved directive", name), name_span));
+    }
+
+    let body = &source[equals + 1..end];
+    let body_start = equals + 1 + body.len() - body.trim_start().len();
+
+    Ok((name, parse_in(source, body_start..end, options)?))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
 }
 
 fn parse_group<'source>(
     lexer: &mut Lexer<'source, Token<'source>>,
+    options: &ParseOptions,
     explicit_close: bool,
+    depth: usize,
 ) -> Result<Value<'source>> {
     // Used to build the text group
     let mut children = Vec::new();
@@ -32,15 +180,32 @@ fn parse_group<'source>(
 
     while let Some(token) = lexer.next() {
         match token {
-            Ok(Token::Text(s)) => current_group.push(Value::Text(s)),
-            Ok(Token::Dot) => current_group.push(Value::Text(".")),
-            Ok(Token::Range) => current_group.push(Value::Text("..")),
+            Ok(Token::Text(s)) => current_group.push(Value::Text(Cow::Borrowed(s))),
+            Ok(Token::Dot) => current_group.push(Value::Text(Cow::Borrowed("."))),
+            Ok(Token::Range) => current_group.push(Value::Text(Cow::Borrowed(".."))),
+            Ok(Token::Escaped(s)) => current_group.push(Value::Text(Cow::Borrowed(s))),
+            Ok(Token::Variable(name)) => {
+                current_group.push(interpolate_variable(lexer, options, name)?)
+            }
+            // Only a '~' starting the pattern or one of its top level alternatives refers to the
+            // home directory, like in a shell
+            Ok(Token::Tilde) if current_group.is_empty() && depth == 1 => {
+                current_group.push(interpolate_home(lexer, options)?)
+            }
+            Ok(Token::Tilde) => current_group.push(Value::Text(Cow::Borrowed("~"))),
             Ok(Token::Comma) => {
                 if !current_group.is_empty() {
                     children.push(ExpandableGroup(take(&mut current_group)));
                 }
             }
-            Ok(Token::ParenOpen) => current_group.push(parse_group(lexer, true)?),
+            Ok(Token::ParenOpen) => {
+                let start = lexer.span().start;
+                check_depth(options, depth + 1, lexer.span())?;
+
+                let group = parse_group(lexer, options, true, depth + 1)?;
+                check_limits(options, &group, start..lexer.span().end)?;
+                current_group.push(group);
+            }
             Ok(Token::ParenClose) if explicit_close => {
                 if !current_group.is_empty() {
                     children.push(ExpandableGroup(current_group))
@@ -52,7 +217,28 @@ fn parse_group<'source>(
                 return Err(("Unexpected group closer ')'".to_owned(), lexer.span()))
             }
             Ok(Token::BracketOpen) => {
-                current_group.push(parse_range(lexer)?);
+                let start = lexer.span().start;
+                check_depth(options, depth + 1, lexer.span())?;
+
+                let range = parse_range(lexer, options, depth + 1)?;
+                check_limits(options, &range, start..lexer.span().end)?;
+                current_group.push(range);
+            }
+            Ok(Token::Directive("file")) => {
+                let start = lexer.span().start;
+
+                let lines = parse_file_directive(lexer, options)?;
+                check_limits(options, &lines, start..lexer.span().end)?;
+                current_group.push(lines);
+            }
+            Ok(Token::Directive("include")) => {
+                return Err((
+                    "'@include' must be on a line of its own in a pattern file".to_owned(),
+                    lexer.span(),
+                ))
+            }
+            Ok(Token::Directive(name)) => {
+                current_group.push(Value::Reference(Cow::Borrowed(name), lexer.span()))
             }
             _ => return Err(("Unexpected token".to_owned(), lexer.span())),
         }
@@ -69,6 +255,102 @@ fn parse_group<'source>(
     Ok(Value::TextGroup(children))
 }
 
+// Fails before descending into a group or range nested deeper than allowed
+fn check_depth(options: &ParseOptions, depth: usize, span: Span) -> Result<()> {
+    if depth <= options.limits.max_depth {
+        return Ok(());
+    }
+
+    let err = LimitError {
+        limit: Limit::Depth,
+        max: options.limits.max_depth as u64,
+        actual: depth as u64,
+        span: Some(span.clone()),
+    };
+    Err((err.to_string(), span))
+}
+
+fn check_limits(options: &ParseOptions, value: &Value, span: Span) -> Result<()> {
+    options
+        .limits
+        .check(value)
+        .map_err(|err| (err.to_string(), span))
+}
+
+fn interpolate_variable<'source>(
+    lexer: &Lexer<'source, Token<'source>>,
+    options: &ParseOptions,
+    name: &str,
+) -> Result<Value<'source>> {
+    let Some(environment) = &options.environment else {
+        return Ok(Value::Text(Cow::Borrowed(lexer.slice())));
+    };
+
+    match environment.var(name) {
+        Some(value) => Ok(Value::Text(Cow::Owned(value))),
+        None if options.strict => Err((
+            format!("Environment variable '{}' is not set", name),
+            lexer.span(),
+        )),
+        None => Ok(Value::Text(Cow::Borrowed(""))),
+    }
+}
+
+fn interpolate_home<'source>(
+    lexer: &Lexer<'source, Token<'source>>,
+    options: &ParseOptions,
+) -> Result<Value<'source>> {
+    let Some(environment) = &options.environment else {
+        return Ok(Value::Text(Cow::Borrowed("~")));
+    };
+
+    match environment.home_dir() {
+        Some(home) => Ok(Value::Text(Cow::Owned(home))),
+        None if options.strict => Err((
+            "Unable to determine the home directory".to_owned(),
+            lexer.span(),
+        )),
+        None => Ok(Value::Text(Cow::Borrowed("~"))),
+    }
+}
+
+// Handle `@file(path)`, the directive token has already been consumed
+fn parse_file_directive<'source>(
+    lexer: &mut Lexer<'source, Token<'source>>,
+    options: &ParseOptions,
+) -> Result<Value<'source>> {
+    let start = lexer.span().start;
+
+    let open = match lexer.next() {
+        Some(Ok(Token::ParenOpen)) => lexer.span(),
+        _ => return Err(("Expected '(' after '@file'".to_owned(), lexer.span())),
+    };
+
+    loop {
+        match lexer.next() {
+            Some(Ok(Token::ParenClose)) => break,
+            Some(Ok(Token::Text(_) | Token::Dot | Token::Range)) => {}
+            Some(_) => return Err(("Unexpected token in '@file' path".to_owned(), lexer.span())),
+            None => return Err(("Expected ')' before end of input".to_owned(), lexer.span())),
+        }
+    }
+
+    let span = start..lexer.span().end;
+    let path = &lexer.source()[open.end..lexer.span().start];
+    if path.is_empty() {
+        return Err(("Expected a path inside '@file()'".to_owned(), span));
+    }
+
+    let lines = options.resolver.resolve(path).map_err(|msg| (msg, span))?;
+
+    Ok(Value::TextGroup(
+        lines
+            .into_iter()
+            .map(|line| ExpandableGroup(vec![Value::Text(Cow::Owned(line))]))
+            .collect(),
+    ))
+}
+
 // Handle ranges
 #[derive(Debug)]
 enum RangeMember<'source> {
@@ -76,7 +358,11 @@ enum RangeMember<'source> {
     Number(u32),
 }
 
-fn parse_range<'source>(lexer: &mut Lexer<'source, Token<'source>>) -> Result<Value<'source>> {
+fn parse_range<'source>(
+    lexer: &mut Lexer<'source, Token<'source>>,
+    options: &ParseOptions,
+    depth: usize,
+) -> Result<Value<'source>> {
     // Used as a precaution to make sure the format is Left - Range (..) - Right
     let mut passed_range_operator = false;
 
@@ -87,7 +373,10 @@ fn parse_range<'source>(lexer: &mut Lexer<'source, Token<'source>>) -> Result<Va
 
     while let Some(token) = lexer.next() {
         match token {
-            Ok(Token::BracketOpen) => ranges.push(parse_range(lexer)?),
+            Ok(Token::BracketOpen) => {
+                check_depth(options, depth + 1, lexer.span())?;
+                ranges.push(parse_range(lexer, options, depth + 1)?)
+            }
             Ok(Token::Text(s)) => {
                 let member = match s.parse::<u32>() {
                     Ok(num) => RangeMember::Number(num),
@@ -157,20 +446,168 @@ fn new_range<'source>(
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//
-//     #[test]
-//     fn test_valid_range_expression() {
-//         let tokens = vec![
-//             Token::Text("start"),
-//             Token::RangeOperator,
-//             Token::Text("end"),
-//             Token::BracketClose,
-//         ];
-//         let mut lexer = Lexer::new(&tokens);
-//         let result = parse_range(&mut lexer);
-//         assert_eq!(result, Ok(Value::RangeExpression(Value::Text("start"), Value::Text("end"))));
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, TextInterpreter};
+    use std::collections::HashMap;
+
+    struct StaticResolver;
+
+    impl ListResolver for StaticResolver {
+        fn resolve(&self, source: &str) -> std::result::Result<Vec<String>, String> {
+            match source {
+                "services.txt" => Ok(vec!["auth".to_owned(), "billing".to_owned()]),
+                _ => Err(format!("No list named '{}'", source)),
+            }
+        }
+    }
+
+    fn options() -> ParseOptions {
+        ParseOptions {
+            resolver: Box::new(StaticResolver),
+            ..ParseOptions::default()
+        }
+    }
+
+    fn env_options(strict: bool) -> ParseOptions {
+        let environment = HashMap::from([
+            ("USER".to_owned(), "jelle".to_owned()),
+            ("HOME".to_owned(), "/home/jelle".to_owned()),
+        ]);
+
+        ParseOptions {
+            environment: Some(Box::new(environment)),
+            strict,
+            ..ParseOptions::default()
+        }
+    }
+
+    #[test]
+    fn file_directive_expands_to_lines() {
+        let value = parse_with("services/@file(services.txt)/[1..2]", &options()).unwrap();
+
+        assert_eq!(
+            TextInterpreter.interpret(&value).unwrap(),
+            vec![
+                "services/auth/1",
+                "services/auth/2",
+                "services/billing/1",
+                "services/billing/2",
+            ]
+        );
+    }
+
+    #[test]
+    fn file_directive_error_points_at_directive() {
+        let (msg, span) = parse_with("a/@file(missing.txt)/b", &options()).unwrap_err();
+
+        assert_eq!(msg, "No list named 'missing.txt'");
+        assert_eq!(span, 2..20);
+    }
+
+    #[test]
+    fn unknown_directive_is_a_reference() {
+        let value = parse_with("a/@envs", &options()).unwrap();
+        let Value::TextGroup(children) = value else {
+            panic!("Expected a text group")
+        };
+        let Value::ExpandableGroup(members) = &children[0] else {
+            panic!("Expected an expandable group")
+        };
+
+        assert!(
+            matches!(&members[1], Value::Reference(name, span) if name == "envs" && *span == (2..7))
+        );
+    }
+
+    #[test]
+    fn definition_spans_are_relative_to_the_line() {
+        let (name, value) = parse_definition("@envs = (Dev,Prod)").unwrap();
+        assert_eq!(name, "envs");
+        assert_eq!(
+            TextInterpreter.interpret(&value).unwrap(),
+            vec!["Dev", "Prod"]
+        );
+
+        let (_, span) = parse_definition("@envs = (Dev,Prod").unwrap_err();
+        assert_eq!(span, 17..17);
+
+        let (msg, _) = parse_definition("@file = a").unwrap_err();
+        assert_eq!(msg, "'@file' is a reserved directive");
+    }
+
+    #[test]
+    fn variables_are_interpolated() {
+        let value = parse_with("~/(${USER},$USER.bak)/~", &env_options(false)).unwrap();
+
+        assert_eq!(
+            TextInterpreter.interpret(&value).unwrap(),
+            vec!["/home/jelle/jelle/~", "/home/jelle/jelle.bak/~"]
+        );
+
+        let value = parse_with("~/a,foo/(~),(a,~)", &env_options(false)).unwrap();
+        assert_eq!(
+            TextInterpreter.interpret(&value).unwrap(),
+            vec!["/home/jelle/a", "foo/~", "a", "~"]
+        );
+    }
+
+    #[test]
+    fn unset_variables_are_empty_unless_strict() {
+        let value = parse_with("a$MISSING/b", &env_options(false)).unwrap();
+        assert_eq!(TextInterpreter.interpret(&value).unwrap(), vec!["a/b"]);
+
+        let (msg, span) = parse_with("a$MISSING/b", &env_options(true)).unwrap_err();
+        assert_eq!(msg, "Environment variable 'MISSING' is not set");
+        assert_eq!(span, 1..9);
+    }
+
+    #[test]
+    fn escapes_and_disabled_interpolation_keep_literals() {
+        let value = parse_with(r"\$USER/\(x\)/~/$USER/a\b", &env_options(false)).unwrap();
+        assert_eq!(
+            TextInterpreter.interpret(&value).unwrap(),
+            vec![r"$USER/(x)/~/jelle/a\b"]
+        );
+
+        let value = parse("~/$USER").unwrap();
+        assert_eq!(TextInterpreter.interpret(&value).unwrap(), vec!["~/$USER"]);
+
+        let value = parse_with("(price$,$5)", &env_options(true)).unwrap();
+        assert_eq!(
+            TextInterpreter.interpret(&value).unwrap(),
+            vec!["price$", "$5"]
+        );
+
+        let value = parse("a\\").unwrap();
+        assert_eq!(TextInterpreter.interpret(&value).unwrap(), vec!["a\\"]);
+    }
+
+    #[test]
+    fn owned_values_outlive_their_source() {
+        let source = String::from("src/(a,b)_[1..2]");
+        let value = parse(&source).unwrap().into_owned();
+        drop(source);
+
+        let expanded = std::thread::spawn(move || TextInterpreter.interpret(&value).unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(expanded, vec!["src/a_1", "src/a_2", "src/b_1", "src/b_2"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn values_round_trip_through_json() {
+        let value = parse("src/(a,b)_[1..3]/@name").unwrap();
+        let json = serde_json::to_string(&value).unwrap();
+        assert!(json.starts_with(r#"{"type":"TextGroup","value":[{"type":"ExpandableGroup""#));
+
+        let owned: OwnedValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&owned).unwrap(), json);
+        assert!(matches!(
+            owned.into_sequence()[5],
+            Value::Reference(ref name, ref span) if name == "name" && *span == (17..22)
+        ));
+    }
+}
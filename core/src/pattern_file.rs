@@ -0,0 +1,449 @@
+use crate::include::{include_target, Sources};
+use crate::lexer::Token;
+use crate::macros::Definitions;
+use crate::parser::{parse_definition_in, parse_in, ParseOptions, Value};
+use logos::{Logos, Span};
+use std::borrow::Cow;
+
+/// A parsed `.pf` pattern file.
+///
+/// Every non-empty line holds a pattern, a `#` at the start of a line or after whitespace
+/// starts a comment. Lines indented below another line are nested under the directories
+/// produced by that line, and lines of the form `@name = pattern` define fragments that
+/// can be referenced anywhere in the file. When loaded through [`Sources`], a line of the
+/// form `@include(path)` inserts the entries of another pattern file at that position.
+#[derive(Debug, Default)]
+pub struct PatternFile<'source> {
+    pub entries: Vec<Entry<'source>>,
+    pub definitions: Definitions<'source>,
+    /// Defined names following an '@' within a word, like `App@envs`, which are kept as text
+    pub warnings: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry<'source> {
+    /// The pattern of this line, prefixed with the patterns of its parents
+    pub value: Value<'source>,
+    /// The file this line was read from, `0` unless it was included
+    pub file: usize,
+    /// The location of the pattern within the file
+    pub span: Span,
+}
+
+impl PatternFile<'_> {
+    /// Copies the entries and definitions, so they can outlive the sources they were parsed from
+    pub fn into_owned(self) -> PatternFile<'static> {
+        PatternFile {
+            entries: self
+                .entries
+                .into_iter()
+                .map(|entry| Entry {
+                    value: entry.value.into_owned(),
+                    file: entry.file,
+                    span: entry.span,
+                })
+                .collect(),
+            definitions: self.definitions.into_owned(),
+            warnings: self.warnings,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    /// The file the error was found in, see [`Sources::files`]
+    pub file: usize,
+    pub message: String,
+    pub span: Span,
+}
+
+pub fn parse(source: &str) -> Result<PatternFile<'_>, Vec<Diagnostic>> {
+    parse_with(source, &ParseOptions::default())
+}
+
+pub fn parse_with<'source>(
+    source: &'source str,
+    options: &ParseOptions,
+) -> Result<PatternFile<'source>, Vec<Diagnostic>> {
+    FileParser::new(None, options).finish(0, source)
+}
+
+/// Parses a pattern file loaded with [`Sources::load`], including the files it refers to
+pub fn parse_sources<'source>(
+    sources: &'source Sources,
+    options: &ParseOptions,
+) -> Result<PatternFile<'source>, Vec<Diagnostic>> {
+    let mut parser = FileParser::new(Some(sources), options);
+    parser.errors.extend_from_slice(sources.errors());
+
+    parser.finish(0, &sources.files()[0].source)
+}
+
+struct FileParser<'source, 'options> {
+    sources: Option<&'source Sources>,
+    options: &'options ParseOptions,
+    definitions: Definitions<'source>,
+    errors: Vec<Diagnostic>,
+    // Names following an '@' within a word, with the file and span they were found at
+    embedded: Vec<(usize, &'source str, Span)>,
+}
+
+impl<'source, 'options> FileParser<'source, 'options> {
+    fn new(sources: Option<&'source Sources>, options: &'options ParseOptions) -> Self {
+        FileParser {
+            sources,
+            options,
+            definitions: Definitions::new(),
+            errors: Vec::new(),
+            embedded: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, file: usize, (message, span): (String, Span)) {
+        self.errors.push(Diagnostic {
+            file,
+            message,
+            span,
+        });
+    }
+
+    // Parses the root file and expands the references of every entry
+    fn finish(
+        mut self,
+        file: usize,
+        source: &'source str,
+    ) -> Result<PatternFile<'source>, Vec<Diagnostic>> {
+        let mut entries = self.parse_file(file, source);
+
+        for entry in &mut entries {
+            match self
+                .definitions
+                .expand_with(&entry.value, &self.options.limits)
+            {
+                Ok(value) => entry.value = value,
+                Err(err) => self.error(entry.file, err),
+            }
+        }
+
+        let warnings = self
+            .embedded
+            .iter()
+            .filter(|(_, name, _)| self.definitions.get(name).is_some())
+            .map(|(file, name, span)| Diagnostic {
+                file: *file,
+                message: format!(
+                    "'@{0}' within a word is text, write '(@{0})' to expand it or '\\@{0}' to keep it",
+                    name
+                ),
+                span: span.clone(),
+            })
+            .collect();
+
+        match self.errors.is_empty() {
+            true => Ok(PatternFile {
+                entries,
+                definitions: self.definitions,
+                warnings,
+            }),
+            false => {
+                self.errors.sort_by_key(|d| (d.file, d.span.start));
+                self.errors.dedup();
+                Err(self.errors)
+            }
+        }
+    }
+
+    fn parse_file(&mut self, file: usize, source: &'source str) -> Vec<Entry<'source>> {
+        let mut entries: Vec<Entry<'source>> = Vec::new();
+
+        // Lines that are parents of the current line, a failed parent hides its children
+        let mut parents: Vec<Parent> = Vec::new();
+
+        for line in lines(source) {
+            while parents.last().is_some_and(|p| p.indent >= line.indent) {
+                parents.pop();
+            }
+
+            self.embedded.extend(
+                embedded_names(source, line.span.clone())
+                    .into_iter()
+                    .map(|(name, span)| (file, name, span)),
+            );
+
+            let text = &source[line.span.clone()];
+            if text.starts_with('@') && is_definition(text) {
+                if line.indent > 0 {
+                    self.error(
+                        file,
+                        ("Definitions can not be nested".to_owned(), line.span),
+                    );
+                    continue;
+                }
+
+                match parse_definition_in(source, line.span, self.options) {
+                    Ok((name, value)) => {
+                        self.definitions.define(name, value);
+                    }
+                    Err(err) => self.error(file, err),
+                }
+                continue;
+            }
+
+            let parent = match parents.last() {
+                Some(Parent { entry: None, .. }) => {
+                    parents.push(Parent {
+                        indent: line.indent,
+                        entry: None,
+                    });
+                    continue;
+                }
+                Some(Parent {
+                    entry: Some(index), ..
+                }) => Some(as_directory(&mut entries[*index], source)),
+                None => None,
+            };
+
+            if include_target(text).is_some() {
+                for entry in self.parse_include(file, &line) {
+                    entries.push(Entry {
+                        value: prefixed(parent.clone(), entry.value),
+                        ..entry
+                    });
+                }
+                continue;
+            }
+
+            match parse_in(source, line.span.clone(), self.options) {
+                Ok(value) => {
+                    parents.push(Parent {
+                        indent: line.indent,
+                        entry: Some(entries.len()),
+                    });
+                    entries.push(Entry {
+                        value: prefixed(parent, value),
+                        file,
+                        span: line.span,
+                    });
+                }
+                Err(err) => {
+                    self.error(file, err);
+                    parents.push(Parent {
+                        indent: line.indent,
+                        entry: None,
+                    });
+                }
+            }
+        }
+
+        entries
+    }
+
+    // Problems loading the include were already reported by `Sources`
+    fn parse_include(&mut self, file: usize, line: &Line) -> Vec<Entry<'source>> {
+        let Some(sources) = self.sources else {
+            let message = "Includes are only supported in pattern files loaded from disk";
+            self.error(file, (message.to_owned(), line.span.clone()));
+            return Vec::new();
+        };
+
+        match sources.included(file, line.span.start) {
+            Some(included) => self.parse_file(included, &sources.files()[included].source),
+            None => Vec::new(),
+        }
+    }
+}
+
+// Names following an '@' within a word, like `envs` in `App@envs`, which are lexed as text rather
+// than references, with the span of the '@' and the name
+fn embedded_names(source: &str, span: Span) -> Vec<(&str, Span)> {
+    let mut names = Vec::new();
+
+    for (token, token_span) in Token::lexer(&source[span.clone()]).spanned() {
+        let Ok(Token::Text(text)) = token else {
+            continue;
+        };
+
+        for (index, _) in text.match_indices('@').filter(|(index, _)| *index > 0) {
+            let rest = &text[index + 1..];
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '-')
+                .unwrap_or(rest.len());
+            if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                let start = span.start + token_span.start + index;
+                names.push((&rest[..len], start..start + 1 + len));
+            }
+        }
+    }
+
+    names
+}
+
+fn prefixed<'source>(parent: Option<Value<'source>>, value: Value<'source>) -> Value<'source> {
+    match parent {
+        Some(parent) => {
+            let mut members = parent.into_sequence();
+            members.extend(value.into_sequence());
+            Value::ExpandableGroup(members)
+        }
+        None => value,
+    }
+}
+
+struct Parent {
+    indent: usize,
+    // Index of the entry this line produced, `None` when it failed to parse
+    entry: Option<usize>,
+}
+
+pub(crate) struct Line {
+    indent: usize,
+    // The pattern on this line, without indentation, comments and trailing whitespace
+    pub(crate) span: Span,
+}
+
+pub(crate) fn lines(source: &str) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    for raw in source.split_inclusive('\n') {
+        let start = offset;
+        offset += raw.len();
+
+        let content = strip_comment(raw.trim_end_matches(['\n', '\r']));
+        let indent = content.len() - content.trim_start().len();
+        let pattern = content.trim();
+        if pattern.is_empty() {
+            continue;
+        }
+
+        lines.push(Line {
+            indent,
+            span: start + indent..start + indent + pattern.len(),
+        });
+    }
+
+    lines
+}
+
+// A '#' starts a comment at the start of a line or after whitespace, unless it is escaped
+fn strip_comment(line: &str) -> &str {
+    let mut previous = None;
+
+    for (index, c) in line.char_indices() {
+        if c == '#' && previous.is_none_or(char::is_whitespace) {
+            return &line[..index];
+        }
+        previous = Some(c);
+    }
+
+    line
+}
+
+fn is_definition(line: &str) -> bool {
+    line.split_once('=')
+        .is_some_and(|(name, _)| !name.trim().contains(['/', '(', '[', ',']))
+}
+
+// Turns the entry into a directory so it can act as a parent, returning its value
+fn as_directory<'source>(entry: &mut Entry<'source>, source: &str) -> Value<'source> {
+    if !source[entry.span.clone()].ends_with('/') && !is_directory(&entry.value) {
+        let value = std::mem::replace(&mut entry.value, Value::TextGroup(Vec::new()));
+        let mut members = value.into_sequence();
+        members.push(Value::Text(Cow::Borrowed("/")));
+        entry.value = Value::ExpandableGroup(members);
+    }
+
+    entry.value.clone()
+}
+
+fn is_directory(value: &Value) -> bool {
+    match value {
+        Value::ExpandableGroup(group) => {
+            matches!(group.last(), Some(Value::Text(text)) if text.ends_with('/'))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, TextInterpreter};
+
+    fn expand(source: &str) -> Vec<String> {
+        parse(source)
+            .unwrap()
+            .entries
+            .iter()
+            .flat_map(|entry| TextInterpreter.interpret(&entry.value).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn indentation_nests_under_parent() {
+        let source = "# Scaffold\nsrc\n  (main,lib).rs # sources\n  tests/\n    it.rs\nREADME.md\n";
+
+        assert_eq!(
+            expand(source),
+            vec![
+                "src/",
+                "src/main.rs",
+                "src/lib.rs",
+                "src/tests/",
+                "src/tests/it.rs",
+                "README.md",
+            ]
+        );
+    }
+
+    #[test]
+    fn definitions_apply_to_the_whole_file() {
+        let source = "config/@envs.json\n@envs = (Dev,Prod)\n";
+
+        assert_eq!(expand(source), vec!["config/Dev.json", "config/Prod.json"]);
+    }
+
+    #[test]
+    fn defined_names_within_words_are_text_with_a_warning() {
+        let source = "@envs = (Dev,Prod)\nconfig/App@envs.json\nuser@host\n";
+        let file = parse(source).unwrap();
+
+        let warnings = file.warnings.iter().map(|d| (&d.message, d.span.clone()));
+        assert_eq!(
+            warnings.collect::<Vec<_>>(),
+            [(
+                &"'@envs' within a word is text, write '(@envs)' to expand it or '\\@envs' to keep it"
+                    .to_owned(),
+                29..34
+            )]
+        );
+        assert_eq!(expand(source), vec!["config/App@envs.json", "user@host"]);
+
+        let source = "@envs = (Dev,Prod)\nconfig/App(@envs).json\nconfig/App\\@envs.json\n";
+        assert!(parse(source).unwrap().warnings.is_empty());
+        assert_eq!(
+            expand(source),
+            vec![
+                "config/AppDev.json",
+                "config/AppProd.json",
+                "config/App@envs.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_hash_is_not_a_comment() {
+        assert_eq!(expand("lang/C\\#/x#y\n"), vec!["lang/C#/x#y"]);
+    }
+
+    #[test]
+    fn errors_are_reported_per_line() {
+        let source = "ok\nbroken)\n  child\nalso(broken\n@missing/x\n";
+        let errors = parse(source).unwrap_err();
+
+        let spans = errors.into_iter().map(|d| d.span).collect::<Vec<_>>();
+        assert_eq!(spans, vec![9..10, 30..30, 31..39]);
+    }
+}
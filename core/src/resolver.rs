@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Supplies the lines behind a `@file(...)` construct.
+///
+/// The parser hands over the raw text between the parentheses and expects the list of
+/// alternatives back, or a message explaining why the list could not be produced.
+pub trait ListResolver {
+    fn resolve(&self, source: &str) -> Result<Vec<String>, String>;
+}
+
+/// Reads lists from text files, one alternative per non-empty line.
+#[derive(Debug, Default)]
+pub struct FileListResolver {
+    base_dir: PathBuf,
+}
+
+impl FileListResolver {
+    pub fn new(base_dir: PathBuf) -> Self {
+        FileListResolver { base_dir }
+    }
+}
+
+impl ListResolver for FileListResolver {
+    fn resolve(&self, source: &str) -> Result<Vec<String>, String> {
+        let path = self.base_dir.join(source);
+        let content = fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read list file '{}': {}", path.display(), err))?;
+
+        Ok(split_lines(&content))
+    }
+}
+
+/// Splits list content into alternatives, ignoring blank lines and surrounding whitespace
+pub fn split_lines(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Supplies the values behind `$NAME`, `${NAME}` and `~`
+pub trait Environment {
+    fn var(&self, name: &str) -> Option<String>;
+
+    fn home_dir(&self) -> Option<String>;
+}
+
+/// Reads variables from the environment of the current process
+#[derive(Debug, Default)]
+pub struct SystemEnvironment;
+
+impl Environment for SystemEnvironment {
+    fn var(&self, name: &str) -> Option<String> {
+        env::var(name).ok()
+    }
+
+    fn home_dir(&self) -> Option<String> {
+        self.var("HOME").or_else(|| self.var("USERPROFILE"))
+    }
+}
+
+impl Environment for HashMap<String, String> {
+    fn var(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+
+    fn home_dir(&self) -> Option<String> {
+        self.var("HOME")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_lines_skips_blank_lines() {
+        let lines = split_lines("auth\n\n  billing \r\n\t\nsearch");
+        assert_eq!(lines, vec!["auth", "billing", "search"]);
+    }
+
+    #[test]
+    fn file_resolver_reports_missing_file() {
+        let resolver = FileListResolver::new(PathBuf::from("does-not-exist"));
+        let err = resolver.resolve("services.txt").unwrap_err();
+        assert!(err.contains("services.txt"));
+    }
+}
@@ -1,11 +1,14 @@
 use crate::search::{TemplateEngine, TemplateMetadata};
-use std::fs::File;
+use bincode::{Decode, Encode};
+use std::fmt;
+use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 use walkdir::WalkDir;
 use yaml_rust::YamlLoader;
 
+#[derive(Debug, Clone)]
 pub struct TemplateOptions {
     pub template_source_dir: PathBuf,
     pub cached_templates_dir: PathBuf,
@@ -13,8 +16,16 @@ pub struct TemplateOptions {
     pub block_size: usize,
 }
 
+impl TemplateOptions {
+    /// The templates and their metadata, next to the index
+    pub fn manifest_path(&self) -> PathBuf {
+        self.index_path.with_extension("manifest")
+    }
+}
+
 pub struct TemplateIndex {
     templates: Vec<CachedTemplate>,
+    errors: Vec<IndexBuildError>,
     options: TemplateOptions,
 }
 
@@ -23,12 +34,22 @@ impl TemplateIndex {
         TemplateIndex {
             options,
             templates: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
     pub fn build(options: TemplateOptions) -> Self {
         let paths = scan_template_dir(options.template_source_dir.to_path_buf());
-        //fs::create_dir_all("/some/dir");
+        if let Err(err) = fs::create_dir_all(&options.cached_templates_dir) {
+            let errors = vec![IndexBuildError::IoError(
+                options.cached_templates_dir.to_path_buf(),
+                err,
+            )];
+            return TemplateIndex {
+                errors,
+                ..TemplateIndex::new(options)
+            };
+        }
 
         let cache_results: Vec<_> = paths
             .into_iter()
@@ -41,19 +62,84 @@ impl TemplateIndex {
             .collect();
 
         let mut templates = Vec::new();
-        let mut errs = Vec::new();
+        let mut errors = Vec::new();
         for cache in cache_results {
             match cache {
                 Ok(x) => {
                     templates.push(x);
                 }
-                Err(e) => errs.push(e),
+                Err(e) => errors.push(e),
             }
         }
 
-        // TODO: Handle Errors
+        TemplateIndex {
+            templates,
+            errors,
+            options,
+        }
+    }
+
+    /// Reads an index written by [`TemplateIndex::write`]
+    pub fn load(options: TemplateOptions) -> Result<Self, IndexBuildError> {
+        let manifest_path = options.manifest_path();
+        let file = File::open(&manifest_path)
+            .map_err(|err| IndexBuildError::IoError(manifest_path.to_path_buf(), err))?;
+
+        let templates =
+            bincode::decode_from_std_read(&mut BufReader::new(file), bincode::config::standard())
+                .map_err(|err| {
+                IndexBuildError::InvalidIndex(format!(
+                    "Failed to read '{}': {}",
+                    manifest_path.display(),
+                    err
+                ))
+            })?;
+
+        Ok(TemplateIndex {
+            templates,
+            errors: Vec::new(),
+            options,
+        })
+    }
+
+    /// Removes the index and the cached templates it lists, returning how many files were removed
+    pub fn clean(options: &TemplateOptions) -> Result<usize, IndexBuildError> {
+        let mut paths = match TemplateIndex::load(options.clone()) {
+            Ok(index) => index.templates.into_iter().map(|t| t.path).collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.push(options.index_path.to_path_buf());
+        paths.push(options.manifest_path());
+
+        let mut removed = 0;
+        for path in paths {
+            match fs::remove_file(&path) {
+                Ok(()) => removed += 1,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(IndexBuildError::IoError(path, err)),
+            }
+        }
+
+        Ok(removed)
+    }
+
+    pub fn templates(&self) -> &[CachedTemplate] {
+        &self.templates
+    }
+
+    /// The templates that could not be indexed while building
+    pub fn errors(&self) -> &[IndexBuildError] {
+        &self.errors
+    }
+
+    /// The content of a cached template, without its metadata
+    pub fn content(&self, index: usize) -> Result<String, IndexBuildError> {
+        let template = self.templates.get(index).ok_or_else(|| {
+            IndexBuildError::InvalidIndex(format!("There is no template {}", index))
+        })?;
 
-        TemplateIndex { templates, options }
+        fs::read_to_string(&template.path)
+            .map_err(|err| IndexBuildError::IoError(template.path.to_path_buf(), err))
     }
 
     pub fn write(&self) -> Result<(), IndexBuildError> {
@@ -67,16 +153,30 @@ impl TemplateIndex {
             if bytes.len() > self.options.block_size {
                 return Err(IndexBuildError::InvalidIndex(format!(
                     "Template location path '{}' is too long for block-size {}",
-                    path.to_string_lossy(), self.options.block_size
+                    path.to_string_lossy(),
+                    self.options.block_size
                 )));
             }
 
             let mut buffer = vec![0; self.options.block_size];
             buffer[..bytes.len()].copy_from_slice(bytes);
-            file.write_all(&buffer)
-                .map_err(|err| IndexBuildError::IoError(self.options.index_path.to_path_buf(), err))?;
+            file.write_all(&buffer).map_err(|err| {
+                IndexBuildError::IoError(self.options.index_path.to_path_buf(), err)
+            })?;
         }
 
+        let manifest_path = self.options.manifest_path();
+        let mut manifest = File::create(&manifest_path)
+            .map_err(|err| IndexBuildError::IoError(manifest_path.to_path_buf(), err))?;
+        bincode::encode_into_std_write(&self.templates, &mut manifest, bincode::config::standard())
+            .map_err(|err| {
+                IndexBuildError::InvalidIndex(format!(
+                    "Failed to write '{}': {}",
+                    manifest_path.display(),
+                    err
+                ))
+            })?;
+
         Ok(())
     }
 
@@ -129,7 +229,7 @@ fn get_raw_metadata<R: BufRead>(reader: &mut R) -> Result<String, MetadataError>
     let mut block_text = String::new();
 
     for line in reader.by_ref().lines() {
-        let line = line.map_err(|err| MetadataError::IoError(err))?;
+        let line = line.map_err(MetadataError::Io)?;
 
         // Look for the start of the block
         if line.trim() == "---" {
@@ -150,7 +250,7 @@ fn get_raw_metadata<R: BufRead>(reader: &mut R) -> Result<String, MetadataError>
     }
 
     // If we finish reading the file without finding the second `---`
-    Err(MetadataError::InvalidMetadataError(
+    Err(MetadataError::Missing(
         "No template metadata found".to_string(),
     ))
 }
@@ -174,26 +274,45 @@ pub struct Template {
     pub content: String,
 }
 
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct CachedTemplate {
+    source: PathBuf,
     path: PathBuf,
     metadata: TemplateMetadata,
 }
 
+impl CachedTemplate {
+    /// The template the cached copy was made from
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn metadata(&self) -> &TemplateMetadata {
+        &self.metadata
+    }
+}
+
 fn cache_template(
     source_path: PathBuf,
     output_path: PathBuf,
 ) -> Result<CachedTemplate, IndexBuildError> {
-    let source_file =
-        File::open(source_path.to_path_buf()).map_err(|err| IndexBuildError::IoError(source_path.to_path_buf(), err))?;
+    let source_file = File::open(&source_path)
+        .map_err(|err| IndexBuildError::IoError(source_path.to_path_buf(), err))?;
 
     let mut reader = BufReader::new(source_file);
 
-    let yaml = get_raw_metadata(&mut reader).map_err(|err| err.to_index_error(source_path.to_path_buf()))?;
+    let yaml = get_raw_metadata(&mut reader)
+        .map_err(|err| err.into_index_error(source_path.to_path_buf()))?;
 
-    let metadata = parse_metadata_yaml(&yaml).map_err(|err| err.to_index_error(source_path.to_path_buf()))?;
+    let metadata = parse_metadata_yaml(&yaml)
+        .map_err(|err| err.into_index_error(source_path.to_path_buf()))?;
 
-    let output_file =
-        File::create(&output_path).map_err(|err| IndexBuildError::IoError(output_path.to_path_buf(), err))?;
+    let output_file = File::create(&output_path)
+        .map_err(|err| IndexBuildError::IoError(output_path.to_path_buf(), err))?;
 
     let mut writer = BufWriter::new(output_file);
     io::copy(&mut reader, &mut writer)
@@ -204,6 +323,7 @@ fn cache_template(
         .map_err(|err| IndexBuildError::IoError(output_path.to_path_buf(), err))?;
 
     Ok(CachedTemplate {
+        source: source_path,
         metadata,
         path: output_path,
     })
@@ -211,7 +331,7 @@ fn cache_template(
 
 fn parse_metadata_yaml(raw_metadata: &str) -> Result<TemplateMetadata, MetadataError> {
     let raw = YamlLoader::load_from_str(raw_metadata).map_err(|err| {
-        MetadataError::MetadataParseError(format!("Failed to parse template YAML metadata: {}", err))
+        MetadataError::Parse(format!("Failed to parse template YAML metadata: {}", err))
     })?;
     let doc = &raw[0];
 
@@ -235,25 +355,81 @@ pub enum IndexBuildError {
     TemplateParseError(PathBuf, String),
 }
 
+impl fmt::Display for IndexBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexBuildError::IoError(path, err) => write!(f, "{}: {}", path.display(), err),
+            IndexBuildError::InvalidIndex(msg) => write!(f, "{}", msg),
+            IndexBuildError::TemplateParseError(path, msg) => {
+                write!(f, "{}: {}", path.display(), msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IndexBuildError {}
+
 #[derive(Debug)]
 enum MetadataError {
-    IoError(io::Error),
-    InvalidMetadataError(String),
-    MetadataParseError(String),
+    Io(io::Error),
+    Missing(String),
+    Parse(String),
 }
 
 impl MetadataError {
-    fn to_index_error(self, template_path: PathBuf) -> IndexBuildError {
+    fn into_index_error(self, template_path: PathBuf) -> IndexBuildError {
         match self {
-            MetadataError::IoError(err) => IndexBuildError::IoError(template_path, err),
-            MetadataError::InvalidMetadataError(err) => IndexBuildError::TemplateParseError(
+            MetadataError::Io(err) => IndexBuildError::IoError(template_path, err),
+            MetadataError::Missing(err) => IndexBuildError::TemplateParseError(
                 template_path,
                 format!("Failed to find metadata on template: {}", err),
             ),
-            MetadataError::MetadataParseError(err) => IndexBuildError::TemplateParseError(
+            MetadataError::Parse(err) => IndexBuildError::TemplateParseError(
                 template_path,
                 format!("Failed to parse template metadata: {}", err),
             ),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_reports_failures_and_round_trips_through_the_manifest() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("templates")).unwrap();
+        fs::write(
+            dir.join("templates/handler.cs"),
+            "---\nsuffix: Handler.cs\ntags: csharp\n---\nclass Handler {}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("templates/broken.cs"), "no metadata\n").unwrap();
+
+        let options = TemplateOptions {
+            template_source_dir: dir.join("templates"),
+            cached_templates_dir: dir.join("cache"),
+            index_path: dir.join("cache/index"),
+            block_size: 128,
+        };
+        let index = TemplateIndex::build(options.clone());
+        assert_eq!(index.templates().len(), 1);
+        assert_eq!(index.errors().len(), 1);
+        assert!(index.errors()[0].to_string().contains("broken.cs"));
+        index.write().unwrap();
+
+        let loaded = TemplateIndex::load(options.clone()).unwrap();
+        assert_eq!(
+            loaded.templates()[0].source(),
+            dir.join("templates/handler.cs")
+        );
+        assert_eq!(loaded.templates()[0].metadata().tags(), ["csharp"]);
+        assert_eq!(loaded.content(0).unwrap(), "class Handler {}\n");
+        assert_eq!(loaded.to_engine().search("UserHandler.cs", None), Some(0));
+
+        assert_eq!(TemplateIndex::clean(&options).unwrap(), 3);
+        assert_eq!(fs::read_dir(dir.join("cache")).unwrap().count(), 0);
+    }
+}
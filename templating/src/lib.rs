@@ -1,4 +1,5 @@
-mod index;
-mod search;
+pub mod index;
+pub mod render;
+pub mod search;
 mod trie;
 mod util;
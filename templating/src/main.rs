@@ -1,12 +1,7 @@
-use crate::index::{TemplateIndex, TemplateOptions};
+use powerfile_templating::index::{TemplateIndex, TemplateOptions};
 use std::path::PathBuf;
 use std::str::FromStr;
 
-mod index;
-mod search;
-mod trie;
-mod util;
-
 fn main() -> std::io::Result<()> {
     let index = TemplateIndex::build(TemplateOptions {
         block_size: 128,
@@ -17,11 +12,11 @@ fn main() -> std::io::Result<()> {
 
     let _ = index.write();
     let engine = index.to_engine();
-    let result = engine.search(&"IRequestHandler", None).unwrap();
+    let result = engine.search("IRequestHandler", None).unwrap();
     println!("search result: {:?}", result);
 
     let res = index
-        .get_templates_path(&mut vec![result])
+        .get_templates_path(&mut [result])
         .expect("TODO: panic message");
 
     println!("search result: {:?}", res);
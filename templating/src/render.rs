@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+/// Replaces every `{{name}}` in `content` by the value of the variable `name`, spaces around the
+/// name are allowed.
+///
+/// Placeholders naming a variable that is not set are left as they are.
+pub fn render(content: &str, variables: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(open) = rest.find("{{") {
+        result.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        let Some(close) = rest.find("}}") else { break };
+        match variables.get(rest[2..close].trim()) {
+            Some(value) => {
+                result.push_str(value);
+                rest = &rest[close + 2..];
+            }
+            None => {
+                result.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_replaces_known_variables_only() {
+        let variables = HashMap::from([
+            ("1".to_string(), "User".to_string()),
+            ("name".to_string(), "UserController.cs".to_string()),
+        ]);
+
+        assert_eq!(
+            render("class {{1}}Controller { } // {{ name }}", &variables),
+            "class UserController { } // UserController.cs"
+        );
+        assert_eq!(render("{{2}} {{1}}{{", &variables), "{{2}} User{{");
+    }
+}
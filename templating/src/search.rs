@@ -1,6 +1,5 @@
 use crate::trie::{Trie, TrieResult};
 use crate::util;
-use bincode;
 use bincode::{Decode, Encode};
 use std::collections::HashMap;
 
@@ -16,7 +15,7 @@ pub struct TemplateEngine {
     tags: Trie<TemplateTrieData>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct TemplateMetadata {
     prefixes: Option<Vec<String>>,
     suffixes: Option<Vec<String>>,
@@ -35,6 +34,24 @@ impl TemplateMetadata {
             tags,
         }
     }
+
+    pub fn prefixes(&self) -> &[String] {
+        self.prefixes.as_deref().unwrap_or_default()
+    }
+
+    pub fn suffixes(&self) -> &[String] {
+        self.suffixes.as_deref().unwrap_or_default()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+}
+
+impl Default for TemplateEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TemplateEngine {
@@ -173,7 +190,7 @@ mod tests {
             },
         ]);
 
-        let result = engine.search(&"MyFavoriteRequestHandler.cs", Some(&vec!["csharp"]));
+        let result = engine.search("MyFavoriteRequestHandler.cs", Some(&vec!["csharp"]));
         assert_eq!(result, Some(0))
     }
 
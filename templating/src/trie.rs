@@ -75,7 +75,7 @@ impl<T> Trie<T> {
         }
 
         if let Some((data, depth)) = last {
-            return Some(TrieResult { value: data, depth });
+            return Some(TrieResult::new(depth, data));
         }
 
         None